@@ -1,3 +1,7 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
 #[derive(PartialEq)]
 enum Sign {
     Rock,
@@ -45,9 +49,9 @@ fn decode_sign(c: char) -> Sign {
     }
 }
 
-fn read_input() -> Vec<Round> {
+fn read_guide<R: BufRead>(reader: R) -> Vec<Round> {
     let mut rounds = Vec::new();
-    for line in std::io::stdin().lines() {
+    for line in reader.lines() {
         let line = line.expect("Failed to read line");
         match line.trim().as_bytes() {
             [opponent, b' ', you] => {
@@ -63,6 +67,10 @@ fn read_input() -> Vec<Round> {
     rounds
 }
 
+fn read_input() -> Vec<Round> {
+    read_guide(io::stdin().lock())
+}
+
 fn score_round(you: &Sign, opponent: &Sign) -> u32 {
     let outcome_cost = if you.wins(opponent) {
         6
@@ -95,8 +103,240 @@ fn part_two(rounds: &[Round]) -> u32 {
         .sum()
 }
 
+struct Guide {
+    name: String,
+    rounds: Vec<Round>,
+}
+
+fn read_guide_file(path: &str) -> Guide {
+    let file = File::open(path).unwrap_or_else(|e| panic!("cannot open guide {}: {}", path, e));
+    Guide {
+        name: path.to_string(),
+        rounds: read_guide(BufReader::new(file)),
+    }
+}
+
+/// What to do when two guides in a tournament have a different number of rounds.
+#[derive(Clone, Copy)]
+enum LengthPolicy {
+    /// Refuse to play guides of different lengths.
+    Strict,
+    /// Only play the rounds both guides have.
+    Truncate,
+    /// The shorter guide forfeits every round it doesn't have.
+    Forfeit,
+}
+
+impl LengthPolicy {
+    fn decode(s: &str) -> Option<LengthPolicy> {
+        match s {
+            "strict" => Some(LengthPolicy::Strict),
+            "truncate" => Some(LengthPolicy::Truncate),
+            "forfeit" => Some(LengthPolicy::Forfeit),
+            _ => None,
+        }
+    }
+}
+
+struct LengthMismatch {
+    first: String,
+    first_len: usize,
+    second: String,
+    second_len: usize,
+}
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "guide {} has {} rounds but guide {} has {}",
+            self.first, self.first_len, self.second, self.second_len
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+struct Standing {
+    name: String,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    points: u32,
+}
+
+impl Standing {
+    fn record(&mut self, you: &Sign, opponent: &Sign) {
+        if you.wins(opponent) {
+            self.wins += 1;
+        } else if opponent.wins(you) {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+        self.points += score_round(you, opponent);
+    }
+
+    fn record_forfeit_win(&mut self, you: &Sign) {
+        self.wins += 1;
+        self.points += 6 + you.cost();
+    }
+
+    fn record_forfeit_loss(&mut self) {
+        self.losses += 1;
+    }
+}
+
+/// Plays every guide against every other guide, round by round, where each
+/// guide plays its own column (`you`) and ignores the opponent column. The
+/// returned leaderboard is sorted by points, then by wins.
+fn tournament(guides: &[Guide], policy: LengthPolicy) -> Result<Vec<Standing>, LengthMismatch> {
+    let mut standings: Vec<_> = guides
+        .iter()
+        .map(|g| Standing {
+            name: g.name.clone(),
+            ..Default::default()
+        })
+        .collect();
+
+    for i in 0..guides.len() {
+        for j in i + 1..guides.len() {
+            let (a, b) = (&guides[i].rounds, &guides[j].rounds);
+            if a.len() != b.len() {
+                if let LengthPolicy::Strict = policy {
+                    return Err(LengthMismatch {
+                        first: guides[i].name.clone(),
+                        first_len: a.len(),
+                        second: guides[j].name.clone(),
+                        second_len: b.len(),
+                    });
+                }
+            }
+
+            for (x, y) in a.iter().zip(b) {
+                standings[i].record(&x.you, &y.you);
+                standings[j].record(&y.you, &x.you);
+            }
+
+            if let LengthPolicy::Forfeit = policy {
+                let (longer, shorter) = if a.len() > b.len() { (i, j) } else { (j, i) };
+                let common = a.len().min(b.len());
+                for round in &guides[longer].rounds[common..] {
+                    standings[longer].record_forfeit_win(&round.you);
+                    standings[shorter].record_forfeit_loss();
+                }
+            }
+        }
+    }
+
+    standings.sort_by(|a, b| {
+        b.points
+            .cmp(&a.points)
+            .then(b.wins.cmp(&a.wins))
+            .then(a.name.cmp(&b.name))
+    });
+    Ok(standings)
+}
+
+fn print_leaderboard(standings: &[Standing]) {
+    let width = standings
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("guide".len());
+    println!("rank  {:<width$}  wins  draws  losses  points", "guide");
+    for (rank, s) in standings.iter().enumerate() {
+        println!(
+            "{:>4}  {:<width$}  {:>4}  {:>5}  {:>6}  {:>6}",
+            rank + 1,
+            s.name,
+            s.wins,
+            s.draws,
+            s.losses,
+            s.points
+        );
+    }
+}
+
+fn run_tournament(args: &[&str]) {
+    let (policy, paths) = match args {
+        [flag, paths @ ..] if flag.starts_with("--lengths=") => {
+            let policy = LengthPolicy::decode(&flag["--lengths=".len()..])
+                .unwrap_or_else(|| panic!("invalid length policy: {}", flag));
+            (policy, paths)
+        }
+        paths => (LengthPolicy::Strict, paths),
+    };
+    if paths.len() < 2 {
+        panic!("a tournament needs at least two guides");
+    }
+
+    let guides: Vec<_> = paths.iter().map(|path| read_guide_file(path)).collect();
+    match tournament(&guides, policy) {
+        Ok(standings) => print_leaderboard(&standings),
+        Err(e) => panic!("{}, use --lengths=truncate or --lengths=forfeit to play anyway", e),
+    }
+}
+
 fn main() {
-    let rounds = read_input();
-    println!("{}", part_one(&rounds));
-    println!("{}", part_two(&rounds));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => {
+            let rounds = read_input();
+            println!("{}", part_one(&rounds));
+            println!("{}", part_two(&rounds));
+        }
+        ["tournament", rest @ ..] => run_tournament(rest),
+        _ => panic!("usage: day02 [tournament [--lengths=strict|truncate|forfeit] GUIDE GUIDE...]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guide(name: &str, text: &str) -> Guide {
+        Guide {
+            name: name.to_string(),
+            rounds: read_guide(text.as_bytes()),
+        }
+    }
+
+    fn summary(standings: &[Standing]) -> Vec<(&str, u32, u32, u32, u32)> {
+        standings
+            .iter()
+            .map(|s| (s.name.as_str(), s.wins, s.draws, s.losses, s.points))
+            .collect()
+    }
+
+    // `a` plays rock then paper, `b` only plays scissors.
+    fn uneven_guides() -> Vec<Guide> {
+        vec![guide("a", "A X\nA Y\n"), guide("b", "C Z\n")]
+    }
+
+    #[test]
+    fn strict_refuses_uneven_guides() {
+        let e = tournament(&uneven_guides(), LengthPolicy::Strict).err().unwrap();
+        assert_eq!(e.to_string(), "guide a has 2 rounds but guide b has 1");
+
+        let even = [guide("a", "A X\n"), guide("b", "A Y\n"), guide("c", "A X\n")];
+        let standings = tournament(&even, LengthPolicy::Strict).ok().unwrap();
+        assert_eq!(
+            summary(&standings),
+            [("b", 2, 0, 0, 16), ("a", 0, 1, 1, 5), ("c", 0, 1, 1, 5)]
+        );
+    }
+
+    #[test]
+    fn truncate_plays_common_rounds() {
+        let standings = tournament(&uneven_guides(), LengthPolicy::Truncate).ok().unwrap();
+        assert_eq!(summary(&standings), [("a", 1, 0, 0, 7), ("b", 0, 0, 1, 3)]);
+    }
+
+    #[test]
+    fn forfeit_awards_missing_rounds() {
+        let standings = tournament(&uneven_guides(), LengthPolicy::Forfeit).ok().unwrap();
+        assert_eq!(summary(&standings), [("a", 2, 0, 0, 15), ("b", 0, 0, 2, 3)]);
+    }
 }
@@ -1,3 +1,44 @@
+use advent_of_code_2022::json;
+use std::io::{self, BufWriter, Write};
+
+/// A set of items packed into a 52-bit mask, where bit `i` is the item with
+/// priority `i + 1`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ItemSet(u64);
+
+impl ItemSet {
+    fn all() -> Self {
+        ItemSet((1 << 52) - 1)
+    }
+
+    fn from_items(items: &[u8]) -> Self {
+        ItemSet(items.iter().fold(0, |mask, &item| {
+            let priority = score_item(item).expect("items must be ascii alphabetic");
+            mask | 1 << (priority - 1)
+        }))
+    }
+
+    fn intersection(self, other: Self) -> Self {
+        ItemSet(self.0 & other.0)
+    }
+
+    fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    fn items(self) -> impl Iterator<Item = u8> {
+        (0..52u8).filter(move |i| self.0 & 1 << i != 0).map(|i| item_from_priority(i + 1))
+    }
+
+    fn first_priority(self) -> Option<u8> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as u8 + 1)
+        }
+    }
+}
+
 struct Backpack {
     items: Vec<u8>,
 }
@@ -7,14 +48,17 @@ impl Backpack {
         &self.items
     }
 
-    fn compartments(&self) -> (&[u8], &[u8]) {
-        self.items.split_at(self.items.len() / 2)
+    fn compartments(&self) -> Option<(&[u8], &[u8])> {
+        if !self.items.len().is_multiple_of(2) {
+            return None;
+        }
+        Some(self.items.split_at(self.items.len() / 2))
     }
 }
 
 fn read_backpacks() -> Vec<Backpack> {
     let mut backpacks = Vec::new();
-    for line in std::io::stdin().lines() {
+    for line in io::stdin().lines() {
         let items = line
             .expect("failed to read line")
             .into_bytes();
@@ -34,38 +78,497 @@ fn score_item(item: u8) -> Option<u8> {
     }
 }
 
-fn find_first_common(first: &[u8], rest: &[&[u8]]) -> Option<u8> {
-    first.iter().find(|&&item| {
-        rest.iter().all(|other| {
-            other.contains(&item)
-        })
-    }).copied()
+fn item_from_priority(priority: u8) -> u8 {
+    match priority {
+        1..=26 => b'a' + priority - 1,
+        27..=52 => b'A' + priority - 27,
+        _ => panic!("invalid priority: {}", priority),
+    }
+}
+
+fn common_items<'a, I>(item_lists: I) -> ItemSet
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    item_lists
+        .into_iter()
+        .fold(ItemSet::all(), |common, items| common.intersection(ItemSet::from_items(items)))
 }
 
 fn part_one(backpacks: &[Backpack]) -> u32 {
     backpacks
         .iter()
-        .map(|b| {
-            let (xs, ys) = b.compartments();
-            find_first_common(xs, &[ys])
-                .map(|x| score_item(x).unwrap() as u32)
-                .unwrap_or_else(|| panic!("invalid backpack"))
+        .enumerate()
+        .map(|(i, b)| {
+            let (xs, ys) = b.compartments().unwrap_or_else(|| {
+                panic!("backpack {} has an odd number of items: {}", i + 1, b.items().len())
+            });
+            common_items([xs, ys])
+                .first_priority()
+                .unwrap_or_else(|| panic!("backpack {} has no item in both compartments", i + 1))
+                as u32
         })
         .sum()
 }
 
-fn part_two(backpacks: &[Backpack]) -> u32 {
-    backpacks.chunks_exact(3)
-        .map(|group|
-            find_first_common(group[0].items(), &[group[1].items(), group[2].items()])
-                .map(|x| score_item(x).unwrap() as u32)
-                .unwrap_or_else(|| panic!("invalid backpack"))
+fn part_two(backpacks: &[Backpack], group_size: usize) -> u32 {
+    if !backpacks.len().is_multiple_of(group_size) {
+        panic!(
+            "{} backpacks cannot be split into groups of {}",
+            backpacks.len(),
+            group_size
+        );
+    }
+
+    backpacks.chunks_exact(group_size)
+        .enumerate()
+        .map(|(i, group)|
+            common_items(group.iter().map(Backpack::items))
+                .first_priority()
+                .unwrap_or_else(|| panic!("group {} has no common item", i + 1))
+                as u32
         )
         .sum()
 }
 
+/// What explain mode found for a single backpack or a single group.
+struct Finding {
+    /// 1-based backpack numbers (input line numbers) this finding is about.
+    backpacks: Vec<usize>,
+    common: Vec<u8>,
+    warnings: Vec<String>,
+}
+
+impl Finding {
+    fn new(backpacks: Vec<usize>, common: ItemSet) -> Self {
+        let mut warnings = Vec::new();
+        match common.len() {
+            0 => warnings.push("no common item".to_string()),
+            1 => (),
+            n => warnings.push(format!("{} common items", n)),
+        }
+        Finding {
+            backpacks,
+            common: common.items().collect(),
+            warnings,
+        }
+    }
+}
+
+struct Report {
+    backpacks: Vec<Finding>,
+    groups: Vec<Finding>,
+    warnings: Vec<String>,
+}
+
+fn explain(backpacks: &[Backpack], group_size: usize) -> Report {
+    let backpack_findings = backpacks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| match b.compartments() {
+            Some((xs, ys)) => Finding::new(vec![i + 1], common_items([xs, ys])),
+            None => Finding {
+                backpacks: vec![i + 1],
+                common: Vec::new(),
+                warnings: vec![format!("odd number of items: {}", b.items().len())],
+            },
+        })
+        .collect();
+
+    let groups = backpacks
+        .chunks_exact(group_size)
+        .enumerate()
+        .map(|(i, group)| {
+            let first = i * group_size + 1;
+            Finding::new(
+                (first..first + group.len()).collect(),
+                common_items(group.iter().map(Backpack::items)),
+            )
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+    let leftover = backpacks.len() % group_size;
+    if leftover != 0 {
+        warnings.push(format!(
+            "the last {} backpack(s) don't form a complete group of {}",
+            leftover, group_size
+        ));
+    }
+
+    Report {
+        backpacks: backpack_findings,
+        groups,
+        warnings,
+    }
+}
+
+fn format_common(common: &[u8]) -> String {
+    if common.is_empty() {
+        return "none".to_string();
+    }
+    common
+        .iter()
+        .map(|&item| format!("{} ({})", item as char, score_item(item).unwrap()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_text(report: &Report) -> String {
+    let mut out = String::new();
+    let mut push_line = |label: String, finding: &Finding| {
+        out.push_str(&format!("{}: {}", label, format_common(&finding.common)));
+        for warning in &finding.warnings {
+            out.push_str(&format!("  warning: {}", warning));
+        }
+        out.push('\n');
+    };
+
+    for finding in &report.backpacks {
+        push_line(format!("backpack {}", finding.backpacks[0]), finding);
+    }
+    for (i, finding) in report.groups.iter().enumerate() {
+        let first = finding.backpacks[0];
+        let last = finding.backpacks[finding.backpacks.len() - 1];
+        push_line(format!("group {} (backpacks {}-{})", i + 1, first, last), finding);
+    }
+    for warning in &report.warnings {
+        out.push_str(&format!("warning: {}\n", warning));
+    }
+    out
+}
+
+fn json_list<T, F>(xs: &[T], f: F) -> String
+where
+    F: Fn(&T) -> String,
+{
+    format!("[{}]", xs.iter().map(f).collect::<Vec<_>>().join(","))
+}
+
+fn finding_json(finding: &Finding) -> String {
+    format!(
+        "{{\"backpacks\":{},\"common\":{},\"warnings\":{}}}",
+        json_list(&finding.backpacks, |b| b.to_string()),
+        json_list(&finding.common, |&item| format!(
+            "{{\"item\":{},\"priority\":{}}}",
+            json::string(&(item as char).to_string()),
+            score_item(item).unwrap()
+        )),
+        json_list(&finding.warnings, |w| json::string(w)),
+    )
+}
+
+fn format_json(report: &Report) -> String {
+    format!(
+        "{{\"backpacks\":{},\"groups\":{},\"warnings\":{}}}",
+        json_list(&report.backpacks, finding_json),
+        json_list(&report.groups, finding_json),
+        json_list(&report.warnings, |w| json::string(w)),
+    )
+}
+
+/// Finds every group of `group_size` backpacks that shares exactly one item.
+/// Groups are returned as sorted backpack indices.
+fn badge_groups(sets: &[ItemSet], group_size: usize) -> Vec<Vec<usize>> {
+    fn extend(
+        sets: &[ItemSet],
+        group_size: usize,
+        group: &mut Vec<usize>,
+        common: ItemSet,
+        groups: &mut Vec<Vec<usize>>,
+    ) {
+        if group.len() == group_size {
+            if common.len() == 1 {
+                groups.push(group.clone());
+            }
+            return;
+        }
+        let next = group.last().map_or(0, |&last| last + 1);
+        for i in next..sets.len() {
+            let common = common.intersection(sets[i]);
+            if common.len() > 0 {
+                group.push(i);
+                extend(sets, group_size, group, common, groups);
+                group.pop();
+            }
+        }
+    }
+
+    let mut groups = Vec::new();
+    extend(sets, group_size, &mut Vec::new(), ItemSet::all(), &mut groups);
+    groups
+}
+
+/// An exact cover search over candidate groups, where every backpack must be
+/// in exactly one chosen group.
+struct BadgeCover<'a> {
+    groups: &'a [Vec<usize>],
+    /// The groups each backpack belongs to.
+    by_member: &'a [Vec<usize>],
+    covered: Vec<bool>,
+    /// How many backpacks of each group are already covered. A group can only
+    /// be chosen while this is zero.
+    blocked: Vec<u32>,
+    /// How many groups that can still be chosen each backpack belongs to.
+    options: Vec<usize>,
+    chosen: Vec<usize>,
+}
+
+impl BadgeCover<'_> {
+    fn choose(&mut self, g: usize) {
+        let by_member = self.by_member;
+        for &i in &self.groups[g] {
+            self.covered[i] = true;
+            for &h in &by_member[i] {
+                self.blocked[h] += 1;
+                if self.blocked[h] == 1 {
+                    self.groups[h].iter().for_each(|&j| self.options[j] -= 1);
+                }
+            }
+        }
+        self.chosen.push(g);
+    }
+
+    fn unchoose(&mut self, g: usize) {
+        let by_member = self.by_member;
+        self.chosen.pop();
+        for &i in &self.groups[g] {
+            self.covered[i] = false;
+            for &h in &by_member[i] {
+                self.blocked[h] -= 1;
+                if self.blocked[h] == 0 {
+                    self.groups[h].iter().for_each(|&j| self.options[j] += 1);
+                }
+            }
+        }
+    }
+
+    /// Returns false if `found` asked to stop.
+    fn search<F>(&mut self, found: &mut F) -> bool
+    where
+        F: FnMut(&[usize]) -> bool,
+    {
+        // Branch on the uncovered backpack with the fewest groups left. If
+        // one has none, nothing below this point can cover it.
+        let next = (0..self.covered.len())
+            .filter(|&i| !self.covered[i])
+            .min_by_key(|&i| self.options[i]);
+        let i = match next {
+            Some(i) => i,
+            None => return found(&self.chosen),
+        };
+        // Try the groups that also cover the most constrained backpacks first.
+        let mut candidates: Vec<_> = self.by_member[i]
+            .iter()
+            .copied()
+            .filter(|&g| self.blocked[g] == 0)
+            .collect();
+        candidates.sort_by_cached_key(|&g| {
+            self.groups[g].iter().map(|&j| self.options[j]).sum::<usize>()
+        });
+        for g in candidates {
+            self.choose(g);
+            let keep_going = self.search(found);
+            self.unchoose(g);
+            if !keep_going {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Enumerates partitions of all `n` backpacks into the given groups, passing
+/// each one to `found` as a list of indices into `groups` as soon as it is
+/// complete. The search stops when `found` returns false. Returns whether
+/// the search was exhaustive.
+fn badge_partitions<F>(n: usize, groups: &[Vec<usize>], mut found: F) -> bool
+where
+    F: FnMut(&[usize]) -> bool,
+{
+    let mut by_member = vec![Vec::new(); n];
+    for (g, group) in groups.iter().enumerate() {
+        group.iter().for_each(|&i| by_member[i].push(g));
+    }
+
+    let mut cover = BadgeCover {
+        groups,
+        by_member: &by_member,
+        covered: vec![false; n],
+        blocked: vec![0; groups.len()],
+        options: by_member.iter().map(Vec::len).collect(),
+        chosen: Vec::new(),
+    };
+    cover.search(&mut found)
+}
+
+/// Writes up to `limit` partitions of `backpacks` into badge groups as they
+/// are found, or why there is none.
+fn discover_badges<W: Write>(
+    backpacks: &[Backpack],
+    group_size: usize,
+    limit: Option<usize>,
+    out: &mut W,
+) -> io::Result<()> {
+    if !backpacks.len().is_multiple_of(group_size) {
+        return writeln!(
+            out,
+            "no valid partition: {} backpacks cannot be split into groups of {}",
+            backpacks.len(),
+            group_size
+        );
+    }
+
+    let sets: Vec<_> = backpacks.iter().map(|b| ItemSet::from_items(b.items())).collect();
+    let groups = badge_groups(&sets, group_size);
+
+    let mut ungroupable = (0..backpacks.len())
+        .filter(|&i| !groups.iter().any(|group| group.contains(&i)))
+        .peekable();
+    if ungroupable.peek().is_some() {
+        let numbers: Vec<_> = ungroupable.map(|i| (i + 1).to_string()).collect();
+        return writeln!(
+            out,
+            "no valid partition: backpack(s) {} belong to no group of {} with exactly one common item",
+            numbers.join(", "),
+            group_size
+        );
+    }
+
+    let mut count = 0;
+    let mut result = Ok(());
+    let exhaustive = badge_partitions(backpacks.len(), &groups, |partition| {
+        let mut partition = partition.to_vec();
+        partition.sort_unstable();
+        let described: Vec<_> = partition
+            .iter()
+            .map(|&g| {
+                let group = &groups[g];
+                let numbers: Vec<_> = group.iter().map(|i| (i + 1).to_string()).collect();
+                let badge = common_items(group.iter().map(|&i| backpacks[i].items()))
+                    .items()
+                    .next()
+                    .unwrap();
+                format!("[{}] badge {}", numbers.join(" "), badge as char)
+            })
+            .collect();
+        count += 1;
+        result = writeln!(out, "partition {}: {}", count, described.join(", "));
+        result.is_ok() && limit.is_none_or(|limit| count < limit)
+    });
+    result?;
+
+    if count == 0 {
+        return writeln!(
+            out,
+            "no valid partition: exhaustive search over {} candidate group(s) found none",
+            groups.len()
+        );
+    }
+    let qualifier = if exhaustive { "" } else { "at least " };
+    writeln!(out, "{}{} valid partition(s)", qualifier, count)
+}
+
+fn parse_group_size(s: &str) -> usize {
+    s.parse().ok().filter(|&n| n > 0)
+        .unwrap_or_else(|| panic!("invalid group size: {}", s))
+}
+
 fn main() {
-    let backpacks = read_backpacks();
-    println!("{}", part_one(&backpacks));
-    println!("{}", part_two(&backpacks));
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["explain", rest @ ..] => {
+            let (json, rest) = match rest {
+                ["--json", rest @ ..] => (true, rest),
+                rest => (false, rest),
+            };
+            let group_size = match rest {
+                [] => 3,
+                [n] => parse_group_size(n),
+                _ => panic!("usage: day03 explain [--json] [GROUP_SIZE]"),
+            };
+            let report = explain(&read_backpacks(), group_size);
+            if json {
+                println!("{}", format_json(&report));
+            } else {
+                print!("{}", format_text(&report));
+            }
+        }
+        ["badges", rest @ ..] => {
+            let (limit, rest) = match rest {
+                ["--all", rest @ ..] => (None, rest),
+                ["--limit", n, rest @ ..] => (
+                    Some(n.parse().ok().filter(|&n| n > 0)
+                        .unwrap_or_else(|| panic!("invalid limit: {}", n))),
+                    rest,
+                ),
+                rest => (Some(1), rest),
+            };
+            let group_size = match rest {
+                [] => 3,
+                [n] => parse_group_size(n),
+                _ => panic!("usage: day03 badges [--all | --limit N] [GROUP_SIZE]"),
+            };
+            let mut out = BufWriter::new(io::stdout().lock());
+            discover_badges(&read_backpacks(), group_size, limit, &mut out)
+                .expect("failed to write output");
+        }
+        [] | [_] => {
+            let group_size = args.first().map_or(3, |n| parse_group_size(n));
+            let backpacks = read_backpacks();
+            println!("{}", part_one(&backpacks));
+            println!("{}", part_two(&backpacks, group_size));
+        }
+        _ => panic!(
+            "usage: day03 [GROUP_SIZE] | day03 explain [--json] [GROUP_SIZE] \
+             | day03 badges [--all | --limit N] [GROUP_SIZE]"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn badges(lines: &[&str], limit: Option<usize>) -> String {
+        let backpacks: Vec<_> = lines
+            .iter()
+            .map(|line| Backpack {
+                items: line.as_bytes().to_vec(),
+            })
+            .collect();
+        let mut out = Vec::new();
+        discover_badges(&backpacks, 3, limit, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn counts_every_partition() {
+        // Any three of these share exactly `a`, and six backpacks split into
+        // two groups of three in 10 ways.
+        let lines = ["ab", "ac", "ad", "ae", "af", "ag"];
+        let all = badges(&lines, None);
+        assert_eq!(all.lines().filter(|l| l.starts_with("partition ")).count(), 10);
+        assert!(all.ends_with("\n10 valid partition(s)\n"), "{}", all);
+
+        let limited = badges(&lines, Some(3));
+        assert_eq!(limited.lines().count(), 4);
+        assert!(limited.ends_with("\nat least 3 valid partition(s)\n"), "{}", limited);
+    }
+
+    #[test]
+    fn proves_there_is_no_partition() {
+        // Every backpack is in some group, but every group needs backpack 5.
+        let lines = ["abe", "cd", "cd", "aef", "ac", "abe"];
+        assert_eq!(
+            badges(&lines, None),
+            "no valid partition: exhaustive search over 4 candidate group(s) found none\n"
+        );
+        assert_eq!(
+            badges(&["ab", "ab", "cd"], None),
+            "no valid partition: backpack(s) 1, 2, 3 belong to no group of 3 \
+             with exactly one common item\n"
+        );
+    }
+}
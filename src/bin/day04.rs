@@ -1,35 +1,6 @@
-use std::str::FromStr;
+use advent_of_code_2022::interval::{Interval, IntervalSet};
 
-struct Range {
-    start: u32,
-    end: u32,
-}
-
-#[derive(Debug, PartialEq, Eq)]
-struct ParseRangeError;
-
-impl FromStr for Range {
-    type Err = ParseRangeError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (l, r) = s.split_once('-').ok_or(ParseRangeError)?;
-        if let (Ok(l), Ok(r)) = (l.parse(), r.parse()) {
-            Ok(Range { start: l, end: r })
-        } else {
-            Err(ParseRangeError)
-        }
-    }
-}
-
-impl Range {
-    fn contains(&self, rhs: &Self) -> bool {
-        self.start <= rhs.start && self.end >= rhs.end
-    }
-
-    fn overlaps(&self, rhs: &Self) -> bool {
-        self.start <= rhs.end && self.end >= rhs.start
-    }
-}
+type Range = Interval<u32>;
 
 fn read_input() -> Vec<(Range, Range)> {
     let mut pairs = Vec::new();
@@ -38,8 +9,8 @@ fn read_input() -> Vec<(Range, Range)> {
         let line = line.expect("failed to read line");
         if let Some((l, r)) = line.split_once(',') {
             pairs.push((
-                l.parse().unwrap_or_else(|_| panic!("failed to parse range: {}", l)),
-                r.parse().unwrap_or_else(|_| panic!("failed to parse range: {}", r)),
+                l.parse().unwrap_or_else(|e| panic!("failed to parse range {}: {}", l, e)),
+                r.parse().unwrap_or_else(|e| panic!("failed to parse range {}: {}", r, e)),
             ));
         } else {
             panic!("invalid input line, cannot be split: {}", line);
@@ -51,7 +22,7 @@ fn read_input() -> Vec<(Range, Range)> {
 
 fn part_one(input: &[(Range, Range)]) -> usize {
     input.iter()
-        .filter(|(l, r)| l.contains(r) || r.contains(l))
+        .filter(|(l, r)| r.is_subset(l) || l.is_subset(r))
         .count()
 }
 
@@ -61,8 +32,293 @@ fn part_two(input: &[(Range, Range)]) -> usize {
         .count()
 }
 
+/// Splits the sections spanned by `ranges` into consecutive segments with
+/// the number of ranges covering each of them.
+fn sweep(ranges: &[&Range]) -> Vec<(Range, usize)> {
+    let mut events: Vec<(u32, isize)> = ranges
+        .iter()
+        .flat_map(|r| [(r.start(), 1), (r.end(), -1)])
+        .collect();
+    events.sort();
+
+    let mut segments = Vec::new();
+    let mut depth: isize = 0;
+    let mut prev = match events.first() {
+        Some(&(at, _)) => at,
+        None => return segments,
+    };
+    for (at, delta) in events {
+        if at > prev {
+            segments.push((Range::new(prev, at).unwrap(), depth as usize));
+            prev = at;
+        }
+        depth += delta;
+    }
+    segments
+}
+
+struct Assignment<'a> {
+    line: usize,
+    side: &'static str,
+    range: &'a Range,
+}
+
+struct Coverage<'a> {
+    max_elves: usize,
+    busiest: IntervalSet<u32>,
+    uncovered: IntervalSet<u32>,
+    redundant: Vec<Assignment<'a>>,
+}
+
+fn coverage(input: &[(Range, Range)]) -> Coverage<'_> {
+    let assignments: Vec<_> = input
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (l, r))| {
+            [
+                Assignment { line: i + 1, side: "left", range: l },
+                Assignment { line: i + 1, side: "right", range: r },
+            ]
+        })
+        .collect();
+    let ranges: Vec<_> = assignments.iter().map(|a| a.range).collect();
+    let segments = sweep(&ranges);
+
+    let max_elves = segments.iter().map(|&(_, n)| n).max().unwrap_or(0);
+    let busiest = segments
+        .iter()
+        .filter(|&&(_, n)| n == max_elves && n > 0)
+        .map(|&(s, _)| s)
+        .collect();
+    let uncovered = segments
+        .iter()
+        .filter(|&&(_, n)| n == 0)
+        .map(|&(s, _)| s)
+        .collect();
+
+    // An assignment is redundant when every one of its sections is covered
+    // by at least one other assignment too.
+    let redundant = assignments
+        .into_iter()
+        .filter(|a| {
+            let first = segments.partition_point(|(s, _)| s.end() <= a.range.start());
+            segments[first..]
+                .iter()
+                .take_while(|(s, _)| s.start() < a.range.end())
+                .all(|&(_, n)| n >= 2)
+        })
+        .collect();
+
+    Coverage {
+        max_elves,
+        busiest,
+        uncovered,
+        redundant,
+    }
+}
+
+fn format_sections(sections: &IntervalSet<u32>) -> String {
+    if sections.is_empty() {
+        return "none".to_string();
+    }
+    sections.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn print_coverage(coverage: &Coverage) {
+    println!(
+        "max elves per section: {} at {}",
+        coverage.max_elves,
+        format_sections(&coverage.busiest)
+    );
+    println!("uncovered sections: {}", format_sections(&coverage.uncovered));
+    println!("redundant assignments: {}", coverage.redundant.len());
+    for a in &coverage.redundant {
+        println!("  line {} {}: {}", a.line, a.side, a.range);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PlanMode {
+    /// Minimize the total distance the range endpoints move.
+    Endpoints,
+    /// Move as few elves as possible, keeping the length of their range.
+    Elves,
+}
+
+/// The total distance the endpoints of `old` moved to get `new`.
+fn endpoint_change(old: &Range, new: &Range) -> u64 {
+    let moved = |x: u32, y: u32| x.abs_diff(y) as u64;
+    moved(old.start(), new.start()) + moved(old.end(), new.end())
+}
+
+/// Shrinks or shifts endpoints as little as possible so that `first` ends
+/// before `second` starts.
+fn place_before(first: &Range, second: &Range) -> Option<(Range, Range)> {
+    let a = first.start() as i64;
+    let b = first.last().unwrap() as i64;
+    let c = second.start() as i64;
+    let d = second.last().unwrap() as i64;
+
+    // The cost of making `split` the first section of `second` is convex and
+    // piecewise linear, so its minimum is at one of the breakpoints.
+    let cost = |split: i64| {
+        (b - (split - 1)).max(0) + (a - (split - 1)).max(0) + (split - c).max(0) + (split - d).max(0)
+    };
+    // Sections start at 1, so `first` needs at least section 1 before `split`.
+    let split = [a + 1, b + 1, c, d]
+        .into_iter()
+        .filter(|&split| split >= 2 && split <= u32::MAX as i64)
+        .min_by_key(|&split| cost(split))?;
+
+    Some((
+        Range::inclusive(a.min(split - 1) as u32, b.min(split - 1) as u32).ok()?,
+        Range::inclusive(c.max(split) as u32, d.max(split) as u32).ok()?,
+    ))
+}
+
+fn separate_endpoints(l: &Range, r: &Range) -> (Range, Range) {
+    let cost = |(nl, nr): &(Range, Range)| endpoint_change(l, nl) + endpoint_change(r, nr);
+    let l_first = place_before(l, r);
+    let r_first = place_before(r, l).map(|(nr, nl)| (nl, nr));
+    [l_first, r_first]
+        .into_iter()
+        .flatten()
+        .min_by_key(cost)
+        .expect("ranges cannot be separated")
+}
+
+/// Moves one of the two ranges, keeping its length, by the smallest
+/// distance that makes it stop overlapping the other one.
+fn separate_elves(l: &Range, r: &Range) -> (Range, Range) {
+    let shifted = |moving: &Range, fixed: &Range| {
+        let len = moving.len() as u32;
+        let before = fixed
+            .start()
+            .checked_sub(len)
+            .filter(|&start| start >= 1)
+            .and_then(|start| Range::new(start, fixed.start()).ok());
+        let after = fixed
+            .end()
+            .checked_add(len)
+            .and_then(|end| Range::new(fixed.end(), end).ok());
+        [before, after].into_iter().flatten().collect::<Vec<_>>()
+    };
+
+    let moved_l = shifted(l, r).into_iter().map(|nl| (nl, *r));
+    let moved_r = shifted(r, l).into_iter().map(|nr| (*l, nr));
+    moved_l
+        .chain(moved_r)
+        .min_by_key(|(nl, nr)| endpoint_change(l, nl) + endpoint_change(r, nr))
+        .expect("ranges cannot be separated")
+}
+
+struct Plan {
+    pairs: Vec<(Range, Range)>,
+    reassigned: usize,
+    endpoint_change: u64,
+}
+
+fn plan(input: &[(Range, Range)], mode: PlanMode) -> Plan {
+    let mut plan = Plan {
+        pairs: Vec::with_capacity(input.len()),
+        reassigned: 0,
+        endpoint_change: 0,
+    };
+    for (l, r) in input {
+        let (nl, nr) = if !l.overlaps(r) {
+            (*l, *r)
+        } else {
+            match mode {
+                PlanMode::Endpoints => separate_endpoints(l, r),
+                PlanMode::Elves => separate_elves(l, r),
+            }
+        };
+        plan.reassigned += (nl != *l) as usize + (nr != *r) as usize;
+        plan.endpoint_change += endpoint_change(l, &nl) + endpoint_change(r, &nr);
+        plan.pairs.push((nl, nr));
+    }
+    plan
+}
+
 fn main() {
-    let input = read_input();
-    println!("{}", part_one(&input));
-    println!("{}", part_two(&input));
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => {
+            let input = read_input();
+            println!("{}", part_one(&input));
+            println!("{}", part_two(&input));
+        }
+        ["coverage"] => print_coverage(&coverage(&read_input())),
+        ["plan", rest @ ..] => {
+            let mode = match rest {
+                [] | ["endpoints"] => PlanMode::Endpoints,
+                ["elves"] => PlanMode::Elves,
+                _ => panic!("usage: day04 plan [endpoints|elves]"),
+            };
+            let plan = plan(&read_input(), mode);
+            for (l, r) in &plan.pairs {
+                println!("{},{}", l, r);
+            }
+            eprintln!(
+                "reassigned {} elves, moved endpoints by {} sections in total",
+                plan.reassigned, plan.endpoint_change
+            );
+        }
+        _ => panic!("usage: day04 [coverage | plan [endpoints|elves]]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(l: &str, r: &str) -> (Range, Range) {
+        (l.parse().unwrap(), r.parse().unwrap())
+    }
+
+    #[test]
+    fn coverage_of_example() {
+        let input = [
+            pair("2-4", "6-8"),
+            pair("2-3", "4-5"),
+            pair("5-7", "7-9"),
+            pair("2-8", "3-7"),
+            pair("6-6", "4-6"),
+            pair("2-6", "4-8"),
+            pair("12-13", "12-13"),
+        ];
+        let coverage = coverage(&input);
+        assert_eq!(coverage.max_elves, 8);
+        assert_eq!(format_sections(&coverage.busiest), "6-6");
+        assert_eq!(format_sections(&coverage.uncovered), "10-11");
+
+        // Only 7-9 has a section nobody else covers; the two identical
+        // assignments each cover the other.
+        let redundant: Vec<_> = coverage.redundant.iter().map(|a| (a.line, a.side)).collect();
+        let expected: Vec<_> = (1..=7)
+            .flat_map(|line| [(line, "left"), (line, "right")])
+            .filter(|&a| a != (3, "right"))
+            .collect();
+        assert_eq!(redundant, expected);
+    }
+
+    #[test]
+    fn plans_never_use_section_zero() {
+        let input = [
+            pair("2-4", "3-5"),
+            pair("1-3", "1-3"),
+            pair("1-1", "1-1"),
+            pair("1-5", "2-3"),
+            pair("2-2", "1-9"),
+        ];
+        for mode in [PlanMode::Endpoints, PlanMode::Elves] {
+            for (l, r) in plan(&input, mode).pairs {
+                assert!(!l.overlaps(&r), "{},{}", l, r);
+                assert!(l.start() >= 1 && r.start() >= 1, "{},{}", l, r);
+            }
+        }
+        let elves = plan(&[pair("2-4", "3-5")], PlanMode::Elves);
+        assert_eq!(elves.pairs, [pair("2-4", "5-7")]);
+    }
+}
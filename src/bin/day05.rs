@@ -1,6 +1,143 @@
-use std::io;
+use advent_of_code_2022::rope::Rope;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn read_stacks() -> Vec<Vec<u8>> {
+/// A crate label, e.g. `A` in `[A]`. Labels may be longer than one character.
+type Crate = String;
+
+#[derive(Debug)]
+struct DrawingError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl fmt::Display for DrawingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Splits a line into its non-whitespace tokens, along with the 0-based
+/// columns of their first and last characters.
+fn tokenize(line: &[char]) -> Vec<(usize, usize, String)> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        if line[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < line.len() && !line[i].is_whitespace() {
+            i += 1;
+        }
+        tokens.push((start, i - 1, line[start..i].iter().collect()));
+    }
+    tokens
+}
+
+/// Parses the crate drawing, i.e. the part of the input before the empty
+/// line. Stacks are found by the column positions of the label line, so
+/// rows may be trimmed and there may be any number of stacks.
+fn parse_drawing(lines: &[String]) -> Result<Vec<Vec<Crate>>, DrawingError> {
+    let error = |line: usize, column: usize, message: String| DrawingError {
+        line: line + 1,
+        column: column + 1,
+        message,
+    };
+
+    let (label_line, rows) = lines
+        .split_last()
+        .ok_or_else(|| error(0, 0, "the drawing is empty".to_string()))?;
+    let label_line_no = rows.len();
+
+    let labels = tokenize(&label_line.chars().collect::<Vec<_>>());
+    if labels.is_empty() {
+        return Err(error(label_line_no, 0, "there are no stack labels".to_string()));
+    }
+    for (i, (start, _, label)) in labels.iter().enumerate() {
+        if label.parse::<usize>().ok() != Some(i + 1) {
+            return Err(error(
+                label_line_no,
+                *start,
+                format!("expected stack label {}, found {:?}", i + 1, label),
+            ));
+        }
+    }
+
+    let mut stacks = vec![Vec::new(); labels.len()];
+    for (height, (line_no, row)) in rows.iter().enumerate().rev().enumerate() {
+        let row: Vec<_> = row.chars().collect();
+        let mut i = 0;
+        while i < row.len() {
+            if row[i].is_whitespace() {
+                i += 1;
+                continue;
+            }
+            if row[i] != '[' {
+                return Err(error(line_no, i, format!("unexpected character {:?}", row[i])));
+            }
+            let start = i;
+            let end = (start + 1..row.len())
+                .find(|&j| row[j] == ']' || row[j] == '[' || row[j].is_whitespace())
+                .filter(|&j| row[j] == ']')
+                .ok_or_else(|| error(line_no, start, "unclosed crate".to_string()))?;
+            if end == start + 1 {
+                return Err(error(line_no, start, "crate without a label".to_string()));
+            }
+            let label: Crate = row[start + 1..end].iter().collect();
+
+            let mut below = labels
+                .iter()
+                .enumerate()
+                .filter(|(_, &(l, r, _))| l <= end && start <= r)
+                .map(|(stack, _)| stack);
+            let stack = match (below.next(), below.next()) {
+                (Some(stack), None) => stack,
+                (None, _) => {
+                    return Err(error(
+                        line_no,
+                        start,
+                        format!("crate {} is not above any stack label", label),
+                    ))
+                }
+                (Some(_), Some(_)) => {
+                    return Err(error(
+                        line_no,
+                        start,
+                        format!("crate {} is above more than one stack label", label),
+                    ))
+                }
+            };
+
+            if stacks[stack].len() < height {
+                return Err(error(
+                    line_no,
+                    start,
+                    format!("crate {} on stack {} has nothing below it", label, stack + 1),
+                ));
+            }
+            if stacks[stack].len() > height {
+                return Err(error(
+                    line_no,
+                    start,
+                    format!("stack {} has more than one crate in this row", stack + 1),
+                ));
+            }
+            stacks[stack].push(label);
+            i = end + 1;
+        }
+    }
+
+    Ok(stacks)
+}
+
+fn read_stacks() -> Vec<Vec<Crate>> {
     let mut stack_map = Vec::new();
     for line in io::stdin().lines() {
         let line = line.expect("input error");
@@ -13,97 +150,1200 @@ fn read_stacks() -> Vec<Vec<u8>> {
         panic!("no input");
     }
 
-    let n = (stack_map[0].len() + 1) / 4;
-    if n > 10 {
-        panic!("too many stacks");
-    }
-
-    let mut stacks = vec![Vec::new(); n];
-    for line in stack_map.iter().rev().skip(1) {
-        let crates = line.as_bytes().iter().skip(1).step_by(4);
-        for (i, c) in crates.enumerate() {
-            if !c.is_ascii_whitespace() {
-                stacks[i].push(*c);
-            }
-        }
-    }
-
-    stacks
+    parse_drawing(&stack_map).unwrap_or_else(|e| panic!("invalid crate drawing: {}", e))
 }
 
+#[derive(Clone, Copy)]
 struct Command {
     from: usize,
     to: usize,
     cnt: usize,
 }
 
-fn decode_command(line: &str) -> Command {
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.cnt, self.from, self.to)
+    }
+}
+
+fn decode_command(line: &str) -> Option<Command> {
     let tokens: Vec<_> = line.split_whitespace().collect();
     match tokens.as_slice() {
         ["move", cnt, "from", from, "to", to] => {
-            Command {
-                from: from.parse().unwrap(),
-                to: to.parse().unwrap(),
-                cnt: cnt.parse().unwrap(),
-            }
+            Some(Command {
+                from: from.parse().ok()?,
+                to: to.parse().ok()?,
+                cnt: cnt.parse().ok()?,
+            })
         }
-        _ => panic!("invalid input, cannot parse command: {}", line),
+        _ => None,
     }
 }
 
 struct Input {
-    stacks: Vec<Vec<u8>>,
+    stacks: Vec<Vec<Crate>>,
     commands: Vec<Command>,
 }
 
 fn read_input() -> Input {
     let stacks = read_stacks();
     let commands = io::stdin().lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(i, line)| {
             let line = line.expect("input error");
             decode_command(&line)
+                .unwrap_or_else(|| panic!("cannot parse command {}: {}", i + 1, line))
         })
         .collect();
     Input { stacks, commands }
 }
 
-fn manipulate<F>(input: &Input, mut crane: F) -> String
+/// How the top of an empty stack is shown in the answer.
+const EMPTY_STACK: &str = "-";
+
+fn format_stacks(stacks: &[Vec<Crate>]) -> String {
+    stacks
+        .iter()
+        .enumerate()
+        .map(|(i, stack)| {
+            if stack.is_empty() {
+                return format!("{}: (empty)", i + 1);
+            }
+            let crates: Vec<_> = stack.iter().map(|c| format!("[{}]", c)).collect();
+            format!("{}: {}", i + 1, crates.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A crane model. Every move is split into lifts, and each lift takes
+/// crates off the top of the source stack and puts them onto the target
+/// stack in the same order.
+trait Crane {
+    fn name(&self) -> String;
+
+    /// How many crates each lift of a move of `cnt` crates takes; the last
+    /// lift takes whatever is left. Returns an error if the crane refuses
+    /// the move.
+    fn lift_size(&self, cnt: usize) -> Result<usize, String>;
+}
+
+/// Moves crates one at a time.
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn name(&self) -> String {
+        "CrateMover 9000".to_string()
+    }
+
+    fn lift_size(&self, _cnt: usize) -> Result<usize, String> {
+        Ok(1)
+    }
+}
+
+/// Moves all crates at once.
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn name(&self) -> String {
+        "CrateMover 9001".to_string()
+    }
+
+    fn lift_size(&self, cnt: usize) -> Result<usize, String> {
+        Ok(cnt.max(1))
+    }
+}
+
+/// Moves at most `max` crates per lift.
+struct BatchCrane {
+    max: usize,
+}
+
+impl Crane for BatchCrane {
+    fn name(&self) -> String {
+        format!("batch crane ({} crates per lift)", self.max)
+    }
+
+    fn lift_size(&self, cnt: usize) -> Result<usize, String> {
+        Ok(self.max.min(cnt).max(1))
+    }
+}
+
+/// Moves all crates at once, but refuses moves of more than `max` crates.
+struct CappedCrane {
+    max: usize,
+}
+
+impl Crane for CappedCrane {
+    fn name(&self) -> String {
+        format!("capped crane (at most {} crates per move)", self.max)
+    }
+
+    fn lift_size(&self, cnt: usize) -> Result<usize, String> {
+        if cnt > self.max {
+            Err(format!("{} can't move {} crates", self.name(), cnt))
+        } else {
+            Ok(cnt.max(1))
+        }
+    }
+}
+
+fn decode_crane(s: &str) -> Option<Box<dyn Crane>> {
+    let parse_max = |max: &str| max.parse().ok().filter(|&max| max > 0);
+    match s.split_once(':') {
+        None if s == "9000" => Some(Box::new(CrateMover9000)),
+        None if s == "9001" => Some(Box::new(CrateMover9001)),
+        Some(("batch", max)) => Some(Box::new(BatchCrane { max: parse_max(max)? })),
+        Some(("capped", max)) => Some(Box::new(CappedCrane { max: parse_max(max)? })),
+        _ => None,
+    }
+}
+
+enum CommandErrorKind {
+    NoSuchStack(usize),
+    NotEnoughCrates { stack: usize, height: usize },
+    Rejected(String),
+}
+
+struct CommandError {
+    /// 1-based position of the command in the command list.
+    index: usize,
+    command: Command,
+    kind: CommandErrorKind,
+    /// The stacks right before the failing command.
+    stacks: Vec<Vec<Crate>>,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "command {} ({}): ", self.index, self.command)?;
+        match self.kind {
+            CommandErrorKind::NoSuchStack(stack) => write!(
+                f,
+                "there is no stack {}, stacks are numbered 1 to {}",
+                stack,
+                self.stacks.len()
+            )?,
+            CommandErrorKind::NotEnoughCrates { stack, height } => {
+                write!(f, "stack {} has only {} crate(s)", stack, height)?
+            }
+            CommandErrorKind::Rejected(ref reason) => write!(f, "{}", reason)?,
+        }
+        write!(f, "\nstacks before the command:\n{}", format_stacks(&self.stacks))
+    }
+}
+
+/// Storage for the stacks. Cranes only need these operations, so the same
+/// rearrangement can run on plain vectors or, for huge inputs, on ropes.
+/// Stacks are 0-based here.
+trait CrateStacks {
+    fn from_vecs(stacks: &[Vec<Crate>]) -> Self;
+
+    fn stack_count(&self) -> usize;
+
+    fn height(&self, stack: usize) -> usize;
+
+    fn top(&self, stack: usize) -> Option<&Crate>;
+
+    /// Takes the top `n` crates off stack `from` and puts them onto stack
+    /// `to`, keeping their order.
+    fn lift(&mut self, from: usize, to: usize, n: usize);
+
+    /// Moves `cnt` crates in lifts of `lift_size` crates, the last lift
+    /// takes whatever is left.
+    fn transfer(&mut self, from: usize, to: usize, cnt: usize, lift_size: usize) {
+        transfer_in_lifts(self, from, to, cnt, lift_size);
+    }
+
+    fn to_vecs(&self) -> Vec<Vec<Crate>>;
+}
+
+fn transfer_in_lifts<S>(stacks: &mut S, from: usize, to: usize, cnt: usize, lift_size: usize)
+where
+    S: CrateStacks + ?Sized,
+{
+    let (full, rest) = (cnt / lift_size, cnt % lift_size);
+    for _ in 0..full {
+        stacks.lift(from, to, lift_size);
+    }
+    if rest > 0 {
+        stacks.lift(from, to, rest);
+    }
+}
+
+/// Reorders a pile the way it lands when moved in lifts of `lift_size`
+/// crates. Each lift takes the next crates off the top of the pile, so the
+/// lifts land in reverse order, each one in order.
+fn arrange_lifts(pile: &mut [Crate], lift_size: usize) {
+    if lift_size < pile.len() {
+        pile.reverse();
+        pile.chunks_mut(lift_size).for_each(<[Crate]>::reverse);
+    }
+}
+
+impl CrateStacks for Vec<Vec<Crate>> {
+    fn from_vecs(stacks: &[Vec<Crate>]) -> Self {
+        stacks.to_vec()
+    }
+
+    fn stack_count(&self) -> usize {
+        self.len()
+    }
+
+    fn height(&self, stack: usize) -> usize {
+        self[stack].len()
+    }
+
+    fn top(&self, stack: usize) -> Option<&Crate> {
+        self[stack].last()
+    }
+
+    fn lift(&mut self, from: usize, to: usize, n: usize) {
+        let from = &mut self[from];
+        let pile: Vec<_> = from.drain(from.len() - n..).collect();
+        self[to].extend(pile);
+    }
+
+    fn transfer(&mut self, from: usize, to: usize, cnt: usize, lift_size: usize) {
+        let from = &mut self[from];
+        let mut pile = from.split_off(from.len() - cnt);
+        arrange_lifts(&mut pile, lift_size);
+        self[to].append(&mut pile);
+    }
+
+    fn to_vecs(&self) -> Vec<Vec<Crate>> {
+        self.clone()
+    }
+}
+
+/// Lifts smaller than this cost a rope more in splits and merges than it
+/// costs a vector to move the crates one by one.
+const MIN_ROPE_LIFT: usize = 512;
+
+/// A stack of crates kept in a rope, so that whole piles move in one splice.
+///
+/// Moving a pile in lifts costs the rope a split and a merge per lift, which
+/// only pays off for big lifts. The first move that needs lifts of fewer
+/// than `MIN_ROPE_LIFT` crates (other than one at a time, which is a single
+/// reverse) turns both of its stacks into vectors for good. So with a crane
+/// like `batch:3`, the rope only helps until every stack has been touched by
+/// such a move; from then on it is exactly as fast as plain vectors.
+enum RopeStack {
+    Rope(Rope<Crate>),
+    Vec(Vec<Crate>),
+}
+
+impl RopeStack {
+    fn len(&self) -> usize {
+        match self {
+            RopeStack::Rope(rope) => rope.len(),
+            RopeStack::Vec(vec) => vec.len(),
+        }
+    }
+
+    fn top(&self) -> Option<&Crate> {
+        match self {
+            RopeStack::Rope(rope) => rope.last(),
+            RopeStack::Vec(vec) => vec.last(),
+        }
+    }
+
+    /// Takes the top `n` crates off the stack as a pile of the same kind.
+    fn take(&mut self, n: usize) -> RopeStack {
+        let at = self.len() - n;
+        match self {
+            RopeStack::Rope(rope) => RopeStack::Rope(rope.split_off(at)),
+            RopeStack::Vec(vec) => RopeStack::Vec(vec.split_off(at)),
+        }
+    }
+
+    fn put(&mut self, pile: RopeStack) {
+        match (self, pile) {
+            (RopeStack::Rope(rope), RopeStack::Rope(mut pile)) => rope.append(&mut pile),
+            (RopeStack::Rope(rope), RopeStack::Vec(pile)) => {
+                rope.append(&mut pile.into_iter().collect())
+            }
+            (RopeStack::Vec(vec), pile) => vec.extend(Vec::from(pile)),
+        }
+    }
+
+    fn reverse(&mut self) {
+        match self {
+            RopeStack::Rope(rope) => rope.reverse(),
+            RopeStack::Vec(vec) => vec.reverse(),
+        }
+    }
+
+    fn fall_back_to_vec(&mut self) {
+        if let RopeStack::Rope(rope) = self {
+            *self = RopeStack::Vec(Vec::from(std::mem::take(rope)));
+        }
+    }
+}
+
+impl From<RopeStack> for Vec<Crate> {
+    fn from(stack: RopeStack) -> Self {
+        match stack {
+            RopeStack::Rope(rope) => Vec::from(rope),
+            RopeStack::Vec(vec) => vec,
+        }
+    }
+}
+
+impl CrateStacks for Vec<RopeStack> {
+    fn from_vecs(stacks: &[Vec<Crate>]) -> Self {
+        stacks
+            .iter()
+            .map(|stack| RopeStack::Rope(stack.iter().cloned().collect()))
+            .collect()
+    }
+
+    fn stack_count(&self) -> usize {
+        self.len()
+    }
+
+    fn height(&self, stack: usize) -> usize {
+        self[stack].len()
+    }
+
+    fn top(&self, stack: usize) -> Option<&Crate> {
+        self[stack].top()
+    }
+
+    fn lift(&mut self, from: usize, to: usize, n: usize) {
+        let pile = self[from].take(n);
+        self[to].put(pile);
+    }
+
+    fn transfer(&mut self, from: usize, to: usize, cnt: usize, lift_size: usize) {
+        if lift_size >= cnt {
+            self.lift(from, to, cnt);
+        } else if lift_size == 1 {
+            // Lifting crates one by one reverses them, which the rope can do
+            // in one go.
+            let mut pile = self[from].take(cnt);
+            pile.reverse();
+            self[to].put(pile);
+        } else if lift_size >= MIN_ROPE_LIFT {
+            transfer_in_lifts(self, from, to, cnt, lift_size);
+        } else {
+            self[from].fall_back_to_vec();
+            self[to].fall_back_to_vec();
+            let mut pile = Vec::from(self[from].take(cnt));
+            arrange_lifts(&mut pile, lift_size);
+            self[to].put(RopeStack::Vec(pile));
+        }
+    }
+
+    fn to_vecs(&self) -> Vec<Vec<Crate>> {
+        self.iter()
+            .map(|stack| match stack {
+                RopeStack::Rope(rope) => rope.iter().cloned().collect(),
+                RopeStack::Vec(vec) => vec.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Checks that both stacks of the command exist and that `source` has
+/// enough crates to take `cmd.cnt` of them.
+fn validate_from<S>(cmd: &Command, source: usize, stacks: &S) -> Result<(), CommandErrorKind>
+where
+    S: CrateStacks,
+{
+    for stack in [cmd.from, cmd.to] {
+        if stack == 0 || stack > stacks.stack_count() {
+            return Err(CommandErrorKind::NoSuchStack(stack));
+        }
+    }
+    let height = stacks.height(source - 1);
+    if cmd.cnt > height {
+        return Err(CommandErrorKind::NotEnoughCrates {
+            stack: source,
+            height,
+        });
+    }
+    Ok(())
+}
+
+fn validate<S: CrateStacks>(cmd: &Command, stacks: &S) -> Result<(), CommandErrorKind> {
+    validate_from(cmd, cmd.from, stacks)
+}
+
+struct Outcome<S = Vec<Vec<Crate>>> {
+    stacks: S,
+    lifts: usize,
+}
+
+/// Moves the crates of a single, already validated, command and returns the
+/// number of lifts it took.
+fn execute<S: CrateStacks>(stacks: &mut S, cmd: &Command, lift_size: usize) -> usize {
+    stacks.transfer(cmd.from - 1, cmd.to - 1, cmd.cnt, lift_size);
+    cmd.cnt.div_ceil(lift_size)
+}
+
+/// Reverts `execute` by doing the same lifts backwards, in reverse order.
+/// The target stack must hold at least `cmd.cnt` crates.
+fn undo<S: CrateStacks>(stacks: &mut S, cmd: &Command, lift_size: usize) {
+    let rest = cmd.cnt % lift_size;
+    if rest > 0 {
+        stacks.lift(cmd.to - 1, cmd.from - 1, rest);
+    }
+    stacks.transfer(cmd.to - 1, cmd.from - 1, cmd.cnt - rest, lift_size);
+}
+
+/// Applies the commands to `stacks` with the given crane, calling `observe`
+/// with the 1-based command index and the stacks after every command.
+/// Returns the total number of lifts.
+fn rearrange<S, F>(
+    stacks: &mut S,
+    commands: &[Command],
+    crane: &dyn Crane,
+    mut observe: F,
+) -> Result<usize, CommandError>
 where
-    F: FnMut(Vec<u8>, &mut Vec<u8>)
+    S: CrateStacks,
+    F: FnMut(usize, &S),
+{
+    let mut lifts = 0;
+
+    for (i, cmd) in commands.iter().enumerate() {
+        let lift_size = validate(cmd, stacks)
+            .and_then(|_| crane.lift_size(cmd.cnt).map_err(CommandErrorKind::Rejected));
+        match lift_size {
+            Ok(lift_size) => {
+                lifts += execute(stacks, cmd, lift_size);
+                observe(i + 1, stacks);
+            }
+            Err(kind) => {
+                return Err(CommandError {
+                    index: i + 1,
+                    command: *cmd,
+                    kind,
+                    stacks: stacks.to_vecs(),
+                })
+            }
+        }
+    }
+
+    Ok(lifts)
+}
+
+fn manipulate(input: &Input, crane: &dyn Crane) -> Result<Outcome, CommandError> {
+    manipulate_in(input, crane)
+}
+
+/// Like `manipulate`, but keeps the stacks in `S` while rearranging them.
+fn manipulate_in<S: CrateStacks>(input: &Input, crane: &dyn Crane) -> Result<Outcome<S>, CommandError> {
+    let mut stacks = S::from_vecs(&input.stacks);
+    let lifts = rearrange(&mut stacks, &input.commands, crane, |_, _| ())?;
+    Ok(Outcome { stacks, lifts })
+}
+
+/// Like `manipulate`, but calls `observe` with the 1-based command index and
+/// the stacks after every command.
+fn manipulate_with<F>(input: &Input, crane: &dyn Crane, mut observe: F) -> Result<Outcome, CommandError>
+where
+    F: FnMut(usize, &[Vec<Crate>]),
 {
     let mut stacks = input.stacks.clone();
+    let lifts = rearrange(&mut stacks, &input.commands, crane, |i, stacks| observe(i, stacks))?;
+    Ok(Outcome { stacks, lifts })
+}
 
-    for cmd in &input.commands {
-        let from = &mut stacks[cmd.from - 1];
-        crane(
-            from.drain(from.len() - cmd.cnt..).collect(),
-            &mut stacks[cmd.to - 1],
-        );
+/// The recorded rearrangement: the stacks at some point in time plus, for
+/// every command, the crates it moved, so that we can step back and forth.
+struct History<'a> {
+    input: &'a Input,
+    crane: &'a dyn Crane,
+    /// The crates each command moved, in the order they landed.
+    moved: Vec<Vec<Crate>>,
+    /// The number of commands applied to `stacks`.
+    position: usize,
+    stacks: Vec<Vec<Crate>>,
+}
+
+impl<'a> History<'a> {
+    fn record(input: &'a Input, crane: &'a dyn Crane) -> Result<Self, CommandError> {
+        let mut moved = Vec::with_capacity(input.commands.len());
+        let outcome = manipulate_with(input, crane, |i, stacks| {
+            let cmd = &input.commands[i - 1];
+            let to = &stacks[cmd.to - 1];
+            moved.push(to[to.len() - cmd.cnt..].to_vec());
+        })?;
+        Ok(History {
+            input,
+            crane,
+            moved,
+            position: input.commands.len(),
+            stacks: outcome.stacks,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.input.commands.len()
+    }
+
+    fn lift_size(&self, cmd: &Command) -> usize {
+        self.crane
+            .lift_size(cmd.cnt)
+            .expect("a recorded command cannot be rejected")
+    }
+
+    /// Reverts the last applied command, returns false at the beginning.
+    fn undo(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        let cmd = &self.input.commands[self.position];
+        let lift_size = self.lift_size(cmd);
+        undo(&mut self.stacks, cmd, lift_size);
+        true
+    }
+
+    /// Applies the next command again, returns false at the end.
+    fn redo(&mut self) -> bool {
+        if self.position == self.len() {
+            return false;
+        }
+        let cmd = &self.input.commands[self.position];
+        let lift_size = self.lift_size(cmd);
+        execute(&mut self.stacks, cmd, lift_size);
+        self.position += 1;
+        true
+    }
+
+    /// Moves to the state after the first `position` commands.
+    fn seek(&mut self, position: usize) {
+        let position = position.min(self.len());
+        while self.position > position {
+            self.undo();
+        }
+        while self.position < position {
+            self.redo();
+        }
+    }
+
+    /// The 1-based index of the last command, up to the current position,
+    /// that moved a crate with the given label.
+    fn last_moved(&self, label: &str) -> Option<usize> {
+        self.moved[..self.position]
+            .iter()
+            .rposition(|pile| pile.iter().any(|c| c == label))
+            .map(|i| i + 1)
+    }
+}
+
+/// Runs the commands backwards, treating `input.stacks` as the final state,
+/// and returns the stacks the rearrangement must have started from.
+fn reconstruct(input: &Input, crane: &dyn Crane) -> Result<Vec<Vec<Crate>>, CommandError> {
+    let mut stacks = input.stacks.clone();
+
+    for (i, cmd) in input.commands.iter().enumerate().rev() {
+        let lift_size = validate_from(cmd, cmd.to, &stacks)
+            .and_then(|_| crane.lift_size(cmd.cnt).map_err(CommandErrorKind::Rejected));
+        match lift_size {
+            Ok(lift_size) => undo(&mut stacks, cmd, lift_size),
+            Err(kind) => {
+                return Err(CommandError {
+                    index: i + 1,
+                    command: *cmd,
+                    kind,
+                    stacks,
+                })
+            }
+        }
+    }
+
+    Ok(stacks)
+}
+
+/// Renders the stacks the way the puzzle draws them, e.g.
+///
+/// ```text
+///     [D]
+/// [N] [C]
+/// [Z] [M] [P]
+///  1   2   3
+/// ```
+fn render_drawing(stacks: &[Vec<Crate>]) -> String {
+    let labels: Vec<_> = (1..=stacks.len()).map(|i| i.to_string()).collect();
+    let width = stacks
+        .iter()
+        .flatten()
+        .map(|c| c.chars().count() + 2)
+        .chain(labels.iter().map(String::len))
+        .max()
+        .unwrap_or(0);
+    let height = stacks.iter().map(Vec::len).max().unwrap_or(0);
+
+    // Crates and labels are centred the same way, so every crate overlaps
+    // the label of its own stack and nothing else.
+    let centre = |text: &str| {
+        let pad = (width - text.chars().count()) / 2;
+        format!("{}{:<w$}", " ".repeat(pad), text, w = width - pad)
+    };
+
+    let mut lines = Vec::with_capacity(height + 1);
+    for row in (0..height).rev() {
+        let cells: Vec<_> = stacks
+            .iter()
+            .map(|stack| match stack.get(row) {
+                Some(c) => centre(&format!("[{}]", c)),
+                None => " ".repeat(width),
+            })
+            .collect();
+        lines.push(cells.join(" ").trim_end().to_string());
     }
+    let cells: Vec<_> = labels.iter().map(|label| centre(label)).collect();
+    lines.push(cells.join(" ").trim_end().to_string());
+    lines.join("\n")
+}
 
-    stacks.iter().map(
-        |stack| *stack.last().unwrap() as char
+fn top_crates<S: CrateStacks>(stacks: &S) -> String {
+    (0..stacks.stack_count()).map(
+        |stack| stacks.top(stack).map_or(EMPTY_STACK, |c| c.as_str())
     ).collect()
 }
 
-fn part_one(input: &Input) -> String {
-    manipulate(
-        input,
-        |pile, to| {
-            for &c in pile.iter().rev() {
-                to.push(c);
+/// Where the stacks are kept while solving.
+#[derive(Clone, Copy)]
+enum Storage {
+    Vec,
+    Rope,
+}
+
+impl Storage {
+    fn decode(s: &str) -> Option<Storage> {
+        match s {
+            "vec" => Some(Storage::Vec),
+            "rope" => Some(Storage::Rope),
+            _ => None,
+        }
+    }
+}
+
+/// Rearranges the stacks with the given crane and returns the top crates
+/// along with the number of lifts it took.
+fn solve(input: &Input, crane: &dyn Crane, storage: Storage) -> Result<(String, usize), CommandError> {
+    fn summary<S: CrateStacks>(outcome: Outcome<S>) -> (String, usize) {
+        (top_crates(&outcome.stacks), outcome.lifts)
+    }
+    match storage {
+        Storage::Vec => manipulate_in::<Vec<Vec<Crate>>>(input, crane).map(summary),
+        Storage::Rope => manipulate_in::<Vec<RopeStack>>(input, crane).map(summary),
+    }
+}
+
+fn part_one(input: &Input, storage: Storage) -> Result<String, CommandError> {
+    solve(input, &CrateMover9000, storage).map(|(top, _)| top)
+}
+
+fn part_two(input: &Input, storage: Storage) -> Result<String, CommandError> {
+    solve(input, &CrateMover9001, storage).map(|(top, _)| top)
+}
+
+enum FrameSink {
+    /// Redraw the terminal in place, pausing between frames.
+    Terminal(Duration),
+    /// Write every frame to a numbered file in a directory.
+    Directory(String),
+}
+
+fn animate(input: &Input, crane: &dyn Crane, every: usize, sink: &FrameSink) -> Result<Outcome, CommandError> {
+    let total = input.commands.len();
+    let mut frame = 0;
+    let mut show = |caption: String, stacks: &[Vec<Crate>]| {
+        let drawing = render_drawing(stacks);
+        match sink {
+            FrameSink::Terminal(delay) => {
+                // Clear the screen and move the cursor to the top left corner.
+                print!("\x1b[2J\x1b[H{}\n\n{}\n", caption, drawing);
+                io::stdout().flush().expect("cannot write frame");
+                thread::sleep(*delay);
             }
-        })
+            FrameSink::Directory(dir) => {
+                let path = Path::new(dir).join(format!("frame_{:05}.txt", frame));
+                fs::write(&path, format!("{}\n\n{}\n", caption, drawing))
+                    .unwrap_or_else(|e| panic!("cannot write {}: {}", path.display(), e));
+            }
+        }
+        frame += 1;
+    };
+
+    show(format!("initial state, {} commands", total), &input.stacks);
+    manipulate_with(input, crane, |i, stacks| {
+        if i % every == 0 || i == total {
+            show(format!("after command {}/{}: {}", i, total, input.commands[i - 1]), stacks);
+        }
+    })
 }
 
-fn part_two(input: &Input) -> String {
-    manipulate(
-        input,
-        |pile, to| to.extend(pile))
+fn run_animation(args: &[&str]) {
+    let usage = "usage: day05 animate [--crane MODEL] [--every N] [--delay MS | --out DIR]";
+    let mut crane: Box<dyn Crane> = Box::new(CrateMover9000);
+    let mut every = 1;
+    let mut delay = Duration::from_millis(100);
+    let mut out = None;
+
+    let mut rest = args;
+    while !rest.is_empty() {
+        rest = match rest {
+            ["--crane", model, tail @ ..] => {
+                crane = decode_crane(model)
+                    .unwrap_or_else(|| panic!("unknown crane model: {}", model));
+                tail
+            }
+            ["--every", n, tail @ ..] => {
+                every = n.parse().ok().filter(|&n| n > 0)
+                    .unwrap_or_else(|| panic!("invalid frame interval: {}", n));
+                tail
+            }
+            ["--delay", ms, tail @ ..] => {
+                delay = Duration::from_millis(
+                    ms.parse().unwrap_or_else(|_| panic!("invalid delay: {}", ms)),
+                );
+                tail
+            }
+            ["--out", dir, tail @ ..] => {
+                out = Some(dir.to_string());
+                tail
+            }
+            _ => panic!("{}", usage),
+        };
+    }
+
+    let sink = match out {
+        Some(dir) => {
+            fs::create_dir_all(&dir).unwrap_or_else(|e| panic!("cannot create {}: {}", dir, e));
+            FrameSink::Directory(dir)
+        }
+        None => FrameSink::Terminal(delay),
+    };
+    let input = read_input();
+    if let Err(e) = animate(&input, crane.as_ref(), every, &sink) {
+        panic!("{}", e);
+    }
 }
 
-fn main() {
+fn run_history(args: &[&str]) {
+    let usage = "usage: day05 history [--crane MODEL] QUERY..., where QUERY is one of \
+                 top STACK AFTER | stacks AFTER | moved CRATE | undo N | redo N";
+    let (crane, mut rest) = match args {
+        ["--crane", model, rest @ ..] => (
+            decode_crane(model).unwrap_or_else(|| panic!("unknown crane model: {}", model)),
+            rest,
+        ),
+        rest => (Box::new(CrateMover9000) as Box<dyn Crane>, rest),
+    };
+    if rest.is_empty() {
+        panic!("{}", usage);
+    }
+    let parse_number = |s: &str| -> usize {
+        s.parse().unwrap_or_else(|_| panic!("expected a number, found {}", s))
+    };
+
+    let input = read_input();
+    let mut history = History::record(&input, crane.as_ref()).unwrap_or_else(|e| panic!("{}", e));
+    while !rest.is_empty() {
+        rest = match rest {
+            ["top", stack, after, tail @ ..] => {
+                let (stack, after) = (parse_number(stack), parse_number(after));
+                history.seek(after);
+                let top = history
+                    .stacks
+                    .get(stack.wrapping_sub(1))
+                    .unwrap_or_else(|| panic!("there is no stack {}", stack))
+                    .last()
+                    .map_or(EMPTY_STACK, |c| c.as_str());
+                println!("top of stack {} after command {}: {}", stack, history.position, top);
+                tail
+            }
+            ["stacks", after, tail @ ..] => {
+                history.seek(parse_number(after));
+                println!("after command {}:\n{}", history.position, render_drawing(&history.stacks));
+                tail
+            }
+            ["moved", label, tail @ ..] => {
+                match history.last_moved(label) {
+                    Some(i) => println!("crate {} last moved in command {}: {}", label, i, input.commands[i - 1]),
+                    None => println!("crate {} never moved up to command {}", label, history.position),
+                }
+                tail
+            }
+            ["undo", n, tail @ ..] => {
+                for _ in 0..parse_number(n) {
+                    if !history.undo() {
+                        break;
+                    }
+                }
+                println!("undone to command {}:\n{}", history.position, render_drawing(&history.stacks));
+                tail
+            }
+            ["redo", n, tail @ ..] => {
+                for _ in 0..parse_number(n) {
+                    if !history.redo() {
+                        break;
+                    }
+                }
+                println!("redone to command {}:\n{}", history.position, render_drawing(&history.stacks));
+                tail
+            }
+            _ => panic!("{}", usage),
+        };
+    }
+}
+
+fn run_reconstruction(args: &[&str]) {
+    let crane = match args {
+        [] => Box::new(CrateMover9000),
+        ["--crane", model] => {
+            decode_crane(model).unwrap_or_else(|| panic!("unknown crane model: {}", model))
+        }
+        _ => panic!("usage: day05 reconstruct [--crane MODEL]"),
+    };
+
     let input = read_input();
-    println!("{}", part_one(&input));
-    println!("{}", part_two(&input));
+    let initial = reconstruct(&input, crane.as_ref()).unwrap_or_else(|e| panic!("{}", e));
+    let drawing = render_drawing(&initial);
+
+    // Make sure the drawing reads back and rearranges into the final state.
+    let lines: Vec<_> = drawing.lines().map(String::from).collect();
+    let replay = Input {
+        stacks: parse_drawing(&lines)
+            .unwrap_or_else(|e| panic!("reconstructed drawing doesn't parse: {}", e)),
+        commands: input.commands.clone(),
+    };
+    let outcome = manipulate(&replay, crane.as_ref())
+        .unwrap_or_else(|e| panic!("reconstructed drawing doesn't replay: {}", e));
+    assert!(
+        outcome.stacks == input.stacks,
+        "reconstructed drawing doesn't lead to the final state"
+    );
+
+    println!("{}\n", drawing);
+    for cmd in &input.commands {
+        println!("{}", cmd);
+    }
+}
+
+/// A small xorshift generator, good enough to make up benchmark inputs.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Makes up a valid input with `crates` crates spread over `stacks` stacks
+/// and `moves` commands, each moving a random part of its source stack.
+fn generate_input(stacks: usize, crates: usize, moves: usize) -> Input {
+    let mut rng = XorShift(0x2022_0005);
+    let mut piles = vec![Vec::new(); stacks];
+    for i in 0..crates {
+        piles[rng.below(stacks)].push(((b'A' + (i % 26) as u8) as char).to_string());
+    }
+
+    let mut heights: Vec<_> = piles.iter().map(Vec::len).collect();
+    let mut commands = Vec::with_capacity(moves);
+    while commands.len() < moves {
+        let from = rng.below(stacks);
+        let to = rng.below(stacks);
+        if from == to || heights[from] == 0 {
+            continue;
+        }
+        let cnt = 1 + rng.below(heights[from]);
+        heights[from] -= cnt;
+        heights[to] += cnt;
+        commands.push(Command {
+            from: from + 1,
+            to: to + 1,
+            cnt,
+        });
+    }
+
+    Input {
+        stacks: piles,
+        commands,
+    }
+}
+
+fn run_benchmark(args: &[&str]) {
+    let usage = "usage: day05 bench [--stacks N] [--crates N] [--moves N] [--crane MODEL]";
+    let (mut stacks, mut crates, mut moves) = (9, 1_000_000, 2_000);
+    let mut cranes: Vec<Box<dyn Crane>> = vec![Box::new(CrateMover9000), Box::new(CrateMover9001)];
+
+    let mut rest = args;
+    while !rest.is_empty() {
+        let parse = |n: &str| -> usize {
+            n.parse().ok().filter(|&n| n > 0).unwrap_or_else(|| panic!("invalid number: {}", n))
+        };
+        rest = match rest {
+            ["--stacks", n, tail @ ..] => {
+                stacks = parse(n).max(2);
+                tail
+            }
+            ["--crates", n, tail @ ..] => {
+                crates = parse(n);
+                tail
+            }
+            ["--moves", n, tail @ ..] => {
+                moves = parse(n);
+                tail
+            }
+            ["--crane", model, tail @ ..] => {
+                cranes = vec![decode_crane(model)
+                    .unwrap_or_else(|| panic!("unknown crane model: {}", model))];
+                tail
+            }
+            _ => panic!("{}", usage),
+        };
+    }
+
+    let input = generate_input(stacks, crates, moves);
+    println!("{} stacks, {} crates, {} moves", stacks, crates, moves);
+    for crane in &cranes {
+        let mut vecs = input.stacks.clone();
+        let start = Instant::now();
+        rearrange(&mut vecs, &input.commands, crane.as_ref(), |_, _| ())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let vec_time = start.elapsed();
+
+        let mut ropes = Vec::<RopeStack>::from_vecs(&input.stacks);
+        let start = Instant::now();
+        rearrange(&mut ropes, &input.commands, crane.as_ref(), |_, _| ())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let rope_time = start.elapsed();
+
+        assert_eq!(top_crates(&vecs), top_crates(&ropes), "representations disagree");
+        println!(
+            "{}: vec {:.3?}, rope {:.3?} ({:.1}x)",
+            crane.name(),
+            vec_time,
+            rope_time,
+            vec_time.as_secs_f64() / rope_time.as_secs_f64()
+        );
+    }
+}
+
+fn run_solve(args: &[&str]) {
+    let usage = "usage: day05 [--crane 9000|9001|batch:K|capped:K] [--stacks rope|vec] \
+                 | day05 animate|history|reconstruct|bench ...";
+    let mut crane = None;
+    let mut storage = Storage::Rope;
+
+    let mut rest = args;
+    while !rest.is_empty() {
+        rest = match rest {
+            ["--crane", model, tail @ ..] => {
+                crane = Some(decode_crane(model)
+                    .unwrap_or_else(|| panic!("unknown crane model: {}", model)));
+                tail
+            }
+            ["--stacks", kind, tail @ ..] => {
+                storage = Storage::decode(kind)
+                    .unwrap_or_else(|| panic!("unknown stack storage: {}", kind));
+                tail
+            }
+            _ => panic!("{}", usage),
+        };
+    }
+
+    let input = read_input();
+    match crane {
+        None => {
+            for answer in [part_one(&input, storage), part_two(&input, storage)] {
+                match answer {
+                    Ok(answer) => println!("{}", answer),
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+        Some(crane) => match solve(&input, crane.as_ref(), storage) {
+            Ok((top, lifts)) => {
+                println!("{}", top);
+                println!("{} lifts with {}", lifts, crane.name());
+            }
+            Err(e) => panic!("{}", e),
+        },
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["animate", rest @ ..] => run_animation(rest),
+        ["history", rest @ ..] => run_history(rest),
+        ["reconstruct", rest @ ..] => run_reconstruction(rest),
+        ["bench", rest @ ..] => run_benchmark(rest),
+        rest => run_solve(rest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stacks(stacks: &[&[&str]]) -> Vec<Vec<Crate>> {
+        stacks
+            .iter()
+            .map(|stack| stack.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    fn round_trip(stacks: &[Vec<Crate>]) -> Vec<Vec<Crate>> {
+        let lines: Vec<_> = render_drawing(stacks).lines().map(String::from).collect();
+        parse_drawing(&lines).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn parse(lines: &[&str]) -> Result<Vec<Vec<Crate>>, DrawingError> {
+        let lines: Vec<_> = lines.iter().map(|line| line.to_string()).collect();
+        parse_drawing(&lines)
+    }
+
+    fn parse_error(lines: &[&str]) -> String {
+        parse(lines).expect_err("the drawing should not parse").to_string()
+    }
+
+    #[test]
+    fn parses_trimmed_rows() {
+        let drawing = ["    [D]", "[N] [C]", "[Z] [M] [P]", " 1   2   3"];
+        assert_eq!(parse(&drawing).unwrap(), stacks(&[&["Z", "N"], &["M", "C", "D"], &["P"]]));
+    }
+
+    #[test]
+    fn parses_more_than_nine_stacks() {
+        let drawing = [
+            "                                    [J]",
+            "[A]                                 [I] [LONG]",
+            " 1   2   3   4   5   6   7   8   9  10  11",
+        ];
+        let mut expected = vec![Vec::new(); 11];
+        expected[0].push("A".to_string());
+        expected[9] = vec!["I".to_string(), "J".to_string()];
+        expected[10].push("LONG".to_string());
+        assert_eq!(parse(&drawing).unwrap(), expected);
+    }
+
+    #[test]
+    fn reports_malformed_drawings() {
+        assert_eq!(parse_error(&["[A] [B", " 1   2"]), "line 1, column 5: unclosed crate");
+        assert_eq!(
+            parse_error(&["[A] [B]", " 1   3"]),
+            "line 2, column 6: expected stack label 2, found \"3\""
+        );
+        assert_eq!(
+            parse_error(&["[N] [WIDE]", "[Z] [M]", " 1   2   3"]),
+            "line 1, column 5: crate WIDE is above more than one stack label"
+        );
+        assert_eq!(
+            parse_error(&["    [D]", "[N]", "[Z]", " 1   2"]),
+            "line 1, column 5: crate D on stack 2 has nothing below it"
+        );
+        assert_eq!(
+            parse_error(&["[A]     [B]", " 1   2"]),
+            "line 1, column 9: crate B is not above any stack label"
+        );
+        assert_eq!(
+            parse_error(&["                  [A][B]", "1 2 3 4 5 6 7 8 9   10"]),
+            "line 1, column 22: stack 10 has more than one crate in this row"
+        );
+        assert_eq!(
+            parse_error(&["[A] x", " 1   2"]),
+            "line 1, column 5: unexpected character 'x'"
+        );
+        assert_eq!(parse_error(&["[]", " 1"]), "line 1, column 1: crate without a label");
+        assert_eq!(parse_error(&["[A]", ""]), "line 2, column 1: there are no stack labels");
+    }
+
+    #[test]
+    fn transfers_like_single_lifts() {
+        let start = stacks(&[&["A", "B", "C", "D", "E", "F", "G"], &["X"]]);
+        for cnt in 0..=7 {
+            for lift_size in 1..=8 {
+                let mut lifted = start.clone();
+                transfer_in_lifts(&mut lifted, 0, 1, cnt, lift_size);
+                let mut transferred = start.clone();
+                transferred.transfer(0, 1, cnt, lift_size);
+                assert_eq!(transferred, lifted, "{} crates, {} per lift", cnt, lift_size);
+            }
+        }
+    }
+
+    #[test]
+    fn rope_stacks_agree_with_vectors() {
+        let input = generate_input(5, 5000, 300);
+        let cranes: [&dyn Crane; 5] = [
+            &CrateMover9000,
+            &CrateMover9001,
+            &BatchCrane { max: 2 },
+            &BatchCrane { max: MIN_ROPE_LIFT },
+            &CappedCrane { max: usize::MAX },
+        ];
+        for crane in cranes {
+            let vecs = manipulate_in::<Vec<Vec<Crate>>>(&input, crane).ok().unwrap();
+            let ropes = manipulate_in::<Vec<RopeStack>>(&input, crane).ok().unwrap();
+            assert!(ropes.stacks.to_vecs() == vecs.stacks, "{}", crane.name());
+            assert_eq!(ropes.lifts, vecs.lifts);
+        }
+    }
+
+    #[test]
+    fn history_seeks_back_to_the_start() {
+        let command = |cnt, from, to| Command { from, to, cnt };
+        let input = Input {
+            stacks: stacks(&[&["Z", "N"], &["M", "C", "D"], &["P"]]),
+            commands: vec![command(1, 2, 1), command(3, 1, 3), command(2, 2, 1), command(1, 1, 2)],
+        };
+        let cranes: [&dyn Crane; 3] = [&CrateMover9000, &CrateMover9001, &BatchCrane { max: 2 }];
+        for crane in cranes {
+            let end = manipulate(&input, crane).ok().unwrap().stacks;
+            let mut history = History::record(&input, crane).ok().unwrap();
+            history.seek(0);
+            assert_eq!(history.stacks, input.stacks, "{}", crane.name());
+            assert!(!history.undo());
+            history.seek(2);
+            history.seek(1);
+            history.seek(input.commands.len());
+            assert_eq!(history.stacks, end, "{}", crane.name());
+            assert!(!history.redo());
+        }
+    }
+
+    #[test]
+    fn renders_the_example() {
+        let example = stacks(&[&["Z", "N"], &["M", "C", "D"], &["P"]]);
+        assert_eq!(render_drawing(&example), "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3");
+        assert_eq!(round_trip(&example), example);
+    }
+
+    #[test]
+    fn round_trips_narrow_crates_under_wide_ones() {
+        let narrow_on_top = stacks(&[&["ABCDE", "A"]]);
+        assert_eq!(round_trip(&narrow_on_top), narrow_on_top);
+        let mixed = stacks(&[&["A"], &["ABCDE", "B"]]);
+        assert_eq!(round_trip(&mixed), mixed);
+    }
+
+    #[test]
+    fn round_trips_mixed_label_widths() {
+        let mut many: Vec<Vec<Crate>> = (1..=12).map(|i| vec![format!("{}", i % 10)]).collect();
+        many[0].push("X".to_string());
+        many[10].push("WIDE".to_string());
+        many[11].clear();
+        assert_eq!(round_trip(&many), many);
+
+        let wide_labels = stacks(&[&["A"], &[], &["B", "C"], &[], &[], &[], &[], &[], &[], &["D"]]);
+        assert_eq!(round_trip(&wide_labels), wide_labels);
+    }
 }
@@ -1,46 +1,395 @@
-use std::io;
+use advent_of_code_2022::marker::{
+    ByteCounter, CharCounter, HashCounter, MarkerScanner, MarkerSpec, SymbolCounter,
+};
+use std::io::{self, BufRead, BufWriter, Write};
 
-fn find_unique_subsequence(s: &[u8], len: usize) -> Option<usize> {
-    let mut start: usize = 0;
-    let mut counts = [0; std::u8::MAX as usize + 1];
+const PACKET_MARKER_LEN: usize = 4;
+const MESSAGE_MARKER_LEN: usize = 14;
 
-    for (end, &b) in s.iter().enumerate() {
-        counts[b as usize] += 1;
+/// A kind of marker to look for, e.g. the start-of-packet marker.
+struct MarkerKind {
+    name: String,
+    spec: MarkerSpec,
+}
+
+/// Turns raw input bytes into symbols. Line breaks never reach a decoder,
+/// `end_line` is called instead.
+trait Decoder {
+    type Symbol;
+
+    fn feed(&mut self, b: u8, symbols: &mut Vec<Self::Symbol>);
+
+    fn end_line(&mut self, symbols: &mut Vec<Self::Symbol>);
+}
+
+struct ByteDecoder;
+
+impl Decoder for ByteDecoder {
+    type Symbol = u8;
+
+    fn feed(&mut self, b: u8, symbols: &mut Vec<u8>) {
+        symbols.push(b);
+    }
 
-        while counts[b as usize] > 1 {
-            counts[s[start] as usize] -= 1;
-            start += 1;
+    fn end_line(&mut self, _symbols: &mut Vec<u8>) {}
+}
+
+/// Decodes UTF-8 incrementally; malformed sequences become U+FFFD.
+#[derive(Default)]
+struct CharDecoder {
+    pending: Vec<u8>,
+    expected: usize,
+}
+
+impl CharDecoder {
+    fn sequence_len(lead: u8) -> Option<usize> {
+        match lead {
+            0x00..=0x7f => Some(1),
+            0xc0..=0xdf => Some(2),
+            0xe0..=0xef => Some(3),
+            0xf0..=0xf7 => Some(4),
+            _ => None,
         }
+    }
+}
+
+impl Decoder for CharDecoder {
+    type Symbol = char;
 
-        if end - start + 1 == len {
-            return Some(start);
+    fn feed(&mut self, b: u8, symbols: &mut Vec<char>) {
+        let continuation = b & 0xc0 == 0x80;
+        if !self.pending.is_empty() && !continuation {
+            self.end_line(symbols);
+        }
+        if self.pending.is_empty() {
+            match Self::sequence_len(b) {
+                Some(len) => self.expected = len,
+                None => {
+                    symbols.push(char::REPLACEMENT_CHARACTER);
+                    return;
+                }
+            }
+        }
+        self.pending.push(b);
+        if self.pending.len() == self.expected {
+            symbols.extend(String::from_utf8_lossy(&self.pending).chars());
+            self.pending.clear();
         }
     }
 
-    None
+    fn end_line(&mut self, symbols: &mut Vec<char>) {
+        if !self.pending.is_empty() {
+            symbols.push(char::REPLACEMENT_CHARACTER);
+            self.pending.clear();
+        }
+    }
 }
 
-fn solve(signal: &[u8], marker_len: usize) -> usize {
-    find_unique_subsequence(signal, marker_len)
-        .expect("the signal doesn't contain a long enough marker")
-        + marker_len
+/// Splits lines into whitespace-separated tokens.
+#[derive(Default)]
+struct TokenDecoder {
+    token: Vec<u8>,
 }
 
-fn part_one(signal: &[u8]) -> usize {
-    solve(signal, 4)
+impl Decoder for TokenDecoder {
+    type Symbol = String;
+
+    fn feed(&mut self, b: u8, symbols: &mut Vec<String>) {
+        if b.is_ascii_whitespace() {
+            self.end_line(symbols);
+        } else {
+            self.token.push(b);
+        }
+    }
+
+    fn end_line(&mut self, symbols: &mut Vec<String>) {
+        if !self.token.is_empty() {
+            symbols.push(String::from_utf8_lossy(&self.token).into_owned());
+            self.token.clear();
+        }
+    }
 }
 
-fn part_two(signal: &[u8]) -> usize {
-    solve(signal, 14)
+/// Tracks the markers of one line of input, i.e. one signal.
+struct Signal<S, C> {
+    line: usize,
+    scanners: Vec<MarkerScanner<S, C>>,
+    first: Vec<Option<usize>>,
+    empty: bool,
+}
+
+impl<S, C: SymbolCounter<S> + Default> Signal<S, C> {
+    fn new(kinds: &[MarkerKind]) -> Self {
+        Signal {
+            line: 1,
+            scanners: kinds.iter().map(|k| MarkerScanner::new(k.spec)).collect(),
+            first: vec![None; kinds.len()],
+            empty: true,
+        }
+    }
+
+    fn next_line(&mut self) {
+        self.line += 1;
+        self.scanners.iter_mut().for_each(MarkerScanner::reset);
+        self.first.iter_mut().for_each(|first| *first = None);
+        self.empty = true;
+    }
+}
+
+fn format_position(position: Option<usize>) -> String {
+    position.map_or("none".to_string(), |p| p.to_string())
+}
+
+/// Scans every line of `input` as a separate signal made of the symbols
+/// `decoder` produces, counted with `C`. With `all` set, every marker
+/// position is written as soon as it is found; otherwise the first marker
+/// of each kind is written per line.
+fn scan_signals<R, W, D, C>(
+    mut input: R,
+    out: &mut W,
+    mut decoder: D,
+    kinds: &[MarkerKind],
+    all: bool,
+) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+    D: Decoder,
+    D::Symbol: Clone,
+    C: SymbolCounter<D::Symbol> + Default,
+{
+    let mut signal: Signal<D::Symbol, C> = Signal::new(kinds);
+    let mut symbols = Vec::new();
+
+    let process = |signal: &mut Signal<D::Symbol, C>, symbols: &mut Vec<D::Symbol>, out: &mut W| {
+        for symbol in symbols.drain(..) {
+            for (i, scanner) in signal.scanners.iter_mut().enumerate() {
+                if let Some(p) = scanner.push(symbol.clone()) {
+                    signal.first[i].get_or_insert(p);
+                    if all {
+                        writeln!(out, "line {}: {} marker at {}", signal.line, kinds[i].name, p)?;
+                    }
+                }
+            }
+        }
+        io::Result::Ok(())
+    };
+    let finish_line = |signal: &Signal<D::Symbol, C>, out: &mut W| {
+        if !all {
+            for &first in &signal.first {
+                writeln!(out, "{}", format_position(first))?;
+            }
+        }
+        io::Result::Ok(())
+    };
+
+    loop {
+        let buf = input.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        for &b in buf {
+            match b {
+                b'\n' => {
+                    decoder.end_line(&mut symbols);
+                    process(&mut signal, &mut symbols, out)?;
+                    finish_line(&signal, out)?;
+                    signal.next_line();
+                }
+                b'\r' => (),
+                _ => {
+                    signal.empty = false;
+                    decoder.feed(b, &mut symbols);
+                    process(&mut signal, &mut symbols, out)?;
+                }
+            }
+        }
+        let n = buf.len();
+        input.consume(n);
+    }
+
+    decoder.end_line(&mut symbols);
+    process(&mut signal, &mut symbols, out)?;
+    if !signal.empty {
+        finish_line(&signal, out)?;
+    }
+    Ok(())
 }
 
 fn main() {
-    let mut input = String::new();
-    io::stdin()
-        .read_line(&mut input)
-        .expect("failed to read input");
+    let usage = "usage: day06 [--all] [--symbols bytes|chars|tokens] \
+                 [--len L [--max-dups K | --min-distinct D]]";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let parse = |n: &str| -> usize {
+        n.parse().unwrap_or_else(|_| panic!("expected a number, found {}", n))
+    };
+    let mut all = false;
+    let mut symbols = "bytes";
+    let mut len = None;
+    let mut max_repeats = None;
+    let mut min_distinct = None;
+
+    let mut rest = args.as_slice();
+    while !rest.is_empty() {
+        rest = match rest {
+            ["--all", tail @ ..] => {
+                all = true;
+                tail
+            }
+            ["--symbols", kind, tail @ ..] => {
+                symbols = kind;
+                tail
+            }
+            ["--len", n, tail @ ..] => {
+                len = Some(parse(n));
+                tail
+            }
+            ["--max-dups", n, tail @ ..] => {
+                max_repeats = Some(parse(n));
+                tail
+            }
+            ["--min-distinct", n, tail @ ..] => {
+                min_distinct = Some(parse(n));
+                tail
+            }
+            _ => panic!("{}", usage),
+        };
+    }
 
-    println!("{}", part_one(input.as_bytes()));
-    println!("{}", part_two(input.as_bytes()));
+    let spec = match (len, max_repeats, min_distinct) {
+        (None, None, None) => None,
+        (Some(len), None, None) => Some(MarkerSpec::unique(len)),
+        (Some(len), Some(k), None) => Some(MarkerSpec::with_max_repeats(len, k)),
+        (Some(len), None, Some(d)) => Some(MarkerSpec::with_min_distinct(len, d)),
+        _ => panic!("{}", usage),
+    };
+    if spec.is_some_and(|spec| spec.len == 0) {
+        panic!("marker length must be positive");
+    }
+
+    let kinds = match spec {
+        Some(spec) => vec![MarkerKind {
+            name: "custom".to_string(),
+            spec,
+        }],
+        None => vec![
+            MarkerKind {
+                name: "start-of-packet".to_string(),
+                spec: MarkerSpec::unique(PACKET_MARKER_LEN),
+            },
+            MarkerKind {
+                name: "start-of-message".to_string(),
+                spec: MarkerSpec::unique(MESSAGE_MARKER_LEN),
+            },
+        ],
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let input = io::stdin().lock();
+    match symbols {
+        "bytes" => scan_signals::<_, _, _, ByteCounter>(input, &mut out, ByteDecoder, &kinds, all),
+        "chars" => {
+            scan_signals::<_, _, _, CharCounter>(input, &mut out, CharDecoder::default(), &kinds, all)
+        }
+        "tokens" => scan_signals::<_, _, _, HashCounter<String>>(
+            input,
+            &mut out,
+            TokenDecoder::default(),
+            &kinds,
+            all,
+        ),
+        _ => panic!("{}", usage),
+    }
+    .expect("failed to process input");
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn kinds(lens: &[usize]) -> Vec<MarkerKind> {
+        lens.iter()
+            .map(|&len| MarkerKind {
+                name: format!("len-{}", len),
+                spec: MarkerSpec::unique(len),
+            })
+            .collect()
+    }
+
+    /// Scans `input` through a tiny buffer, so that lines and multi-byte
+    /// characters get split across reads.
+    fn scan<D, C>(input: &[u8], decoder: D, lens: &[usize], all: bool) -> String
+    where
+        D: Decoder,
+        D::Symbol: Clone,
+        C: SymbolCounter<D::Symbol> + Default,
+    {
+        let mut out = Vec::new();
+        let input = BufReader::with_capacity(3, input);
+        scan_signals::<_, _, _, C>(input, &mut out, decoder, &kinds(lens), all).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn scan_bytes(input: &str, lens: &[usize], all: bool) -> String {
+        scan::<_, ByteCounter>(input.as_bytes(), ByteDecoder, lens, all)
+    }
+
+    #[test]
+    fn first_markers_per_line() {
+        let input = "mjqjpqmgbljsphdztnvjfqwrcgsmlb\r\n\
+                     bvwbjplbgvbhsrlpgdmjqwftvncz\r\n\
+                     \r\n\
+                     nppdvjthqldpwncqszvftbrmjlhg";
+        assert_eq!(scan_bytes(input, &[4, 14], false), "7\n19\n5\n23\nnone\nnone\n6\n23\n");
+        // A trailing line break doesn't start another signal.
+        assert_eq!(scan_bytes("abcd\n", &[4], false), "4\n");
+        assert_eq!(scan_bytes("", &[4], false), "");
+        assert_eq!(scan_bytes("\n\n", &[4], false), "none\nnone\n");
+    }
+
+    #[test]
+    fn all_markers_per_line() {
+        let input = "aabcd\r\n\nabab\nxyz";
+        assert_eq!(
+            scan_bytes(input, &[3], true),
+            "line 1: len-3 marker at 4\n\
+             line 1: len-3 marker at 5\n\
+             line 4: len-3 marker at 3\n"
+        );
+    }
+
+    #[test]
+    fn chars_do_not_run_across_lines() {
+        let chars = |input: &[u8], lens: &[usize]| {
+            scan::<_, CharCounter>(input, CharDecoder::default(), lens, false)
+        };
+        assert_eq!(chars("ααβγδ\n".as_bytes(), &[4]), "5\n");
+        assert_eq!(scan_bytes("ααβγδ\n", &[4], false), "none\n");
+
+        // The first line ends in the middle of an α, which becomes U+FFFD;
+        // the rest of it starts the next line and is malformed too.
+        assert_eq!(chars(b"a\xce\n\xb1a\n", &[2]), "2\n2\n");
+        assert_eq!(chars(b"aa\xce", &[2]), "3\n");
+        assert_eq!(chars(b"\xce\xb1\xce\xb1", &[2]), "none\n");
+    }
+
+    #[test]
+    fn tokens() {
+        let input = b"up up down left right\n  a b   a c \n";
+        let tokens = scan::<_, HashCounter<String>>(input, TokenDecoder::default(), &[4], false);
+        assert_eq!(tokens, "5\nnone\n");
+        let all = scan::<_, HashCounter<String>>(input, TokenDecoder::default(), &[2], true);
+        assert_eq!(
+            all,
+            "line 1: len-2 marker at 3\n\
+             line 1: len-2 marker at 4\n\
+             line 1: len-2 marker at 5\n\
+             line 2: len-2 marker at 2\n\
+             line 2: len-2 marker at 3\n\
+             line 2: len-2 marker at 4\n"
+        );
+    }
+}
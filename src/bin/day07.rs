@@ -1,47 +1,370 @@
-use std::collections::HashMap;
-use std::io;
+use advent_of_code_2022::interval::{Interval, IntervalSet};
+use advent_of_code_2022::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path};
 
-fn compute_dir_sizes() -> HashMap<String, u32> {
-    let mut dir_sizes = HashMap::new();
-    let mut cwd: Vec<String> = Vec::new();
+type NodeId = usize;
 
-    for line in io::stdin().lines() {
-        let line = line.expect("failed to read line");
-        let tokens : Vec<_> = line.split_whitespace().collect();
-        match tokens.as_slice() {
-            ["$", "cd", dir] => {
-                match *dir {
-                    "/"  => cwd = vec![".".to_string()],
-                    ".." => {
-                        cwd.pop();
-                    },
-                    _ => {
-                        cwd.push(dir.to_string());
+const ROOT: NodeId = 0;
+
+enum NodeKind {
+    Dir { children: BTreeMap<String, NodeId> },
+    File { size: u64 },
+}
+
+struct Node {
+    name: String,
+    parent: Option<NodeId>,
+    kind: NodeKind,
+}
+
+/// The filesystem reconstructed from a terminal transcript. Nodes live in
+/// an arena and refer to each other by index; the root is always `ROOT`.
+struct Filesystem {
+    nodes: Vec<Node>,
+    /// The size of all files together. Every other sum of sizes is at most
+    /// this, so once it is known to fit in a u64 they do too.
+    total: u64,
+}
+
+impl Filesystem {
+    fn new() -> Self {
+        Filesystem {
+            nodes: vec![Node {
+                name: "/".to_string(),
+                parent: None,
+                kind: NodeKind::Dir {
+                    children: BTreeMap::new(),
+                },
+            }],
+            total: 0,
+        }
+    }
+
+    fn children(&self, dir: NodeId) -> Option<&BTreeMap<String, NodeId>> {
+        match &self.nodes[dir].kind {
+            NodeKind::Dir { children } => Some(children),
+            NodeKind::File { .. } => None,
+        }
+    }
+
+    fn is_dir(&self, id: NodeId) -> bool {
+        self.children(id).is_some()
+    }
+
+    fn child(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        self.children(dir).and_then(|children| children.get(name).copied())
+    }
+
+    fn insert(&mut self, parent: NodeId, name: &str, kind: NodeKind) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            parent: Some(parent),
+            kind,
+        });
+        match &mut self.nodes[parent].kind {
+            NodeKind::Dir { children } => children.insert(name.to_string(), id),
+            NodeKind::File { .. } => unreachable!("files have no children"),
+        };
+        id
+    }
+
+    /// Returns the directory `name` in `parent`, creating it if it wasn't
+    /// seen before.
+    fn add_dir(&mut self, parent: NodeId, name: &str) -> Result<NodeId, String> {
+        match self.child(parent, name) {
+            Some(id) if self.is_dir(id) => Ok(id),
+            Some(id) => Err(format!("{} is a file, not a directory", self.path(id))),
+            None => Ok(self.insert(
+                parent,
+                name,
+                NodeKind::Dir {
+                    children: BTreeMap::new(),
+                },
+            )),
+        }
+    }
+
+    /// Records the file `name` in `parent`. Listing the same file again
+    /// doesn't add it twice.
+    fn add_file(&mut self, parent: NodeId, name: &str, size: u64) -> Result<NodeId, String> {
+        let existing = self.child(parent, name);
+        let old_size = match existing.map(|id| &self.nodes[id].kind) {
+            Some(NodeKind::Dir { .. }) => {
+                return Err(format!("{} is a directory, not a file", self.path(existing.unwrap())))
+            }
+            Some(NodeKind::File { size }) => *size,
+            None => 0,
+        };
+        self.total = (self.total - old_size)
+            .checked_add(size)
+            .ok_or_else(|| "the total size of all files doesn't fit in 64 bits".to_string())?;
+
+        match existing {
+            Some(id) => {
+                self.nodes[id].kind = NodeKind::File { size };
+                Ok(id)
+            }
+            None => Ok(self.insert(parent, name, NodeKind::File { size })),
+        }
+    }
+
+    fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent
+    }
+
+    fn path(&self, id: NodeId) -> String {
+        let mut names = Vec::new();
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            names.push(self.nodes[node].name.as_str());
+            node = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    /// The total size of a file or of everything below a directory.
+    fn size(&self, id: NodeId) -> u64 {
+        match &self.nodes[id].kind {
+            NodeKind::File { size } => *size,
+            NodeKind::Dir { children } => children.values().map(|&child| self.size(child)).sum(),
+        }
+    }
+
+    /// The sizes of all nodes at once, indexed by node id. Children are always
+    /// created after their parent, so a single backwards pass is enough.
+    fn sizes(&self) -> Vec<u64> {
+        let mut sizes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| match node.kind {
+                NodeKind::File { size } => size,
+                NodeKind::Dir { .. } => 0,
+            })
+            .collect();
+        for id in (1..self.nodes.len()).rev() {
+            let parent = self.nodes[id].parent.unwrap();
+            sizes[parent] += sizes[id];
+        }
+        sizes
+    }
+
+    fn dirs(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..self.nodes.len()).filter(|&id| self.is_dir(id))
+    }
+
+    /// Looks up an absolute or relative path without creating anything.
+    fn resolve(&self, cwd: NodeId, path: &str) -> Result<NodeId, String> {
+        let mut id = if path.starts_with('/') { ROOT } else { cwd };
+        for segment in path.split('/').filter(|&segment| !segment.is_empty()) {
+            if !self.is_dir(id) {
+                return Err(format!("{}: not a directory", path));
+            }
+            id = match segment {
+                "." => id,
+                ".." => self
+                    .parent(id)
+                    .ok_or_else(|| format!("{}: cannot go above /", path))?,
+                name => self
+                    .child(id, name)
+                    .ok_or_else(|| format!("{}: no such file or directory", path))?,
+            };
+        }
+        Ok(id)
+    }
+
+    /// `id` and everything below it, parents before their children.
+    fn walk(&self, id: NodeId) -> Vec<NodeId> {
+        let mut order = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            if let Some(children) = self.children(id) {
+                stack.extend(children.values().rev());
+            }
+        }
+        order
+    }
+}
+
+/// Something wrong with a line of the transcript. Fatal issues leave the
+/// filesystem ambiguous and stop the puzzle solutions; the others are only
+/// reported by `validate`.
+#[derive(Debug)]
+struct Issue {
+    line: usize,
+    message: String,
+    fatal: bool,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.fatal { "error" } else { "warning" };
+        write!(f, "line {}: {}: {}", self.line, severity, self.message)
+    }
+}
+
+/// Replays a transcript line by line, building the filesystem and noting
+/// every inconsistency on the way. A line with a fatal issue is skipped.
+struct Replay {
+    fs: Filesystem,
+    cwd: NodeId,
+    /// Whether the last command was an `ls`, so that output is expected.
+    in_ls: bool,
+    /// Directories that showed up in some `ls` output.
+    listed: HashSet<NodeId>,
+    issues: Vec<Issue>,
+    line: usize,
+}
+
+impl Replay {
+    fn new() -> Self {
+        Replay {
+            fs: Filesystem::new(),
+            cwd: ROOT,
+            in_ls: false,
+            listed: HashSet::from([ROOT]),
+            issues: Vec::new(),
+            line: 0,
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        self.issues.push(Issue {
+            line: self.line,
+            message,
+            fatal: false,
+        });
+    }
+
+    fn fail(&mut self, message: String) {
+        self.issues.push(Issue {
+            line: self.line,
+            message,
+            fatal: true,
+        });
+    }
+
+    /// Resolves a `cd` argument against the current directory. Paths may be
+    /// absolute or relative and have any number of segments, e.g. `/a/b`,
+    /// `a/b` or `../c`. Directories that weren't listed yet are created.
+    fn change_dir(&mut self, path: &str) -> Result<NodeId, String> {
+        let mut dir = if path.starts_with('/') { ROOT } else { self.cwd };
+        for segment in path.split('/') {
+            dir = match segment {
+                "" | "." => dir,
+                ".." => self
+                    .fs
+                    .parent(dir)
+                    .ok_or_else(|| format!("cannot go above / with cd {}", path))?,
+                name => {
+                    let child = self.fs.add_dir(dir, name)?;
+                    if !self.listed.contains(&child) {
+                        self.warn(format!("cd into {}, which was never listed", self.fs.path(child)));
                     }
+                    child
                 }
+            };
+        }
+        Ok(dir)
+    }
+
+    fn command(&mut self, command: &[&str]) {
+        self.in_ls = false;
+        match command {
+            ["cd", path] => match self.change_dir(path) {
+                Ok(dir) => self.cwd = dir,
+                Err(message) => self.fail(message),
+            },
+            ["ls"] => self.in_ls = true,
+            ["ls", options @ ..] => {
+                self.warn(format!("ls with unsupported arguments: {}", options.join(" ")));
+                self.in_ls = true;
             }
-            ["$", "ls"] => (),
-            ["dir", _dirname] => (),
-            [size, _filename] => {
-                let mut dir = String::new();
-                for seg in &cwd {
-                    dir.push_str(&format!("/{}", seg));
-                    let dir_size = dir_sizes.entry(dir.clone()).or_insert(0);
-                    *dir_size += size.parse::<u32>()
-                        .unwrap_or_else(|_| panic!("failed to parse size: {}", size));
+            _ => self.fail(format!("unknown command: {}", command.join(" "))),
+        }
+    }
+
+    fn output(&mut self, entry: &[&str]) {
+        if !self.in_ls {
+            self.warn("output outside of an ls".to_string());
+        }
+        match entry {
+            ["dir", dirname] => match self.fs.add_dir(self.cwd, dirname) {
+                Ok(dir) => {
+                    self.listed.insert(dir);
+                }
+                Err(message) => self.fail(message),
+            },
+            [size, filename] => {
+                let size = match size.parse::<u64>() {
+                    Ok(size) => size,
+                    Err(_) => return self.fail(format!("failed to parse size: {}", size)),
+                };
+                if let Some(NodeKind::File { size: old }) =
+                    self.fs.child(self.cwd, filename).map(|id| &self.fs.nodes[id].kind)
+                {
+                    if *old != size {
+                        let path = self.fs.path(self.fs.child(self.cwd, filename).unwrap());
+                        self.warn(format!("{} was listed with size {} before, now {}", path, old, size));
+                    }
+                }
+                if let Err(message) = self.fs.add_file(self.cwd, filename, size) {
+                    self.fail(message);
                 }
             }
-            _ => panic!("something is wrong with this line: {}", line)
+            _ => self.fail(format!("something is wrong with this line: {}", entry.join(" "))),
+        }
+    }
+}
+
+/// Builds the filesystem from a transcript, along with every issue found.
+fn replay<I>(lines: I) -> (Filesystem, Vec<Issue>)
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut replay = Replay::new();
+    for line in lines {
+        replay.line += 1;
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => replay.warn("blank line".to_string()),
+            ["$", command @ ..] => replay.command(command),
+            entry => replay.output(entry),
         }
     }
+    (replay.fs, replay.issues)
+}
+
+fn build_filesystem<I>(lines: I) -> Result<Filesystem, Issue>
+where
+    I: IntoIterator<Item = String>,
+{
+    let (fs, issues) = replay(lines);
+    match issues.into_iter().find(|issue| issue.fatal) {
+        Some(issue) => Err(issue),
+        None => Ok(fs),
+    }
+}
+
+fn read_lines() -> impl Iterator<Item = String> {
+    io::stdin().lines().map(|line| line.expect("failed to read line"))
+}
 
-    dir_sizes
+fn read_filesystem() -> Filesystem {
+    build_filesystem(read_lines()).unwrap_or_else(|e| panic!("invalid transcript: {}", e))
 }
 
-fn part_one(dir_sizes: &HashMap<String, u32>) -> u32 {
-    const AT_MOST: u32 = 100_000;
-    dir_sizes.iter()
-        .filter_map(|(_, &size)| {
+fn part_one(fs: &Filesystem) -> u64 {
+    const AT_MOST: u64 = 100_000;
+    fs.dirs()
+        .filter_map(|dir| {
+            let size = fs.size(dir);
             if size < AT_MOST {
                 Some(size)
             } else {
@@ -51,15 +374,41 @@ fn part_one(dir_sizes: &HashMap<String, u32>) -> u32 {
         .sum()
 }
 
-fn part_two(dir_sizes: &HashMap<String, u32>) -> u32 {
-    const DISK_SIZE: u32 = 70_000_000;
-    const SIZE_REQUIRED_FOR_UPDATE: u32 = 30_000_000;
+/// How much space there is and how much of it has to be free.
+#[derive(Clone, Copy)]
+struct DiskModel {
+    disk_size: u64,
+    required_free: u64,
+}
+
+impl Default for DiskModel {
+    fn default() -> Self {
+        DiskModel {
+            disk_size: 70_000_000,
+            required_free: 30_000_000,
+        }
+    }
+}
+
+impl DiskModel {
+    fn free(&self, fs: &Filesystem) -> u64 {
+        self.disk_size
+            .checked_sub(fs.size(ROOT))
+            .unwrap_or_else(|| panic!("the files take more than the disk size"))
+    }
 
-    let free = DISK_SIZE - *dir_sizes.get("/.").unwrap();
+    /// How much more space has to be freed.
+    fn needed(&self, fs: &Filesystem) -> u64 {
+        self.required_free.saturating_sub(self.free(fs))
+    }
+}
 
-    dir_sizes.iter()
-        .filter_map(|(_, &size)| {
-            if size >= SIZE_REQUIRED_FOR_UPDATE - free {
+fn part_two(fs: &Filesystem, model: &DiskModel) -> u64 {
+    let needed = model.needed(fs);
+    fs.dirs()
+        .filter_map(|dir| {
+            let size = fs.size(dir);
+            if size >= needed {
                 Some(size)
             } else {
                 None
@@ -69,8 +418,666 @@ fn part_two(dir_sizes: &HashMap<String, u32>) -> u32 {
         .unwrap_or_else(|| panic!("no directory is large enough"))
 }
 
+/// Finds the set of directories whose deletion frees at least `needed` with
+/// as little deleted as possible, which is `/` alone when nothing less will
+/// do. No directory in the plan is inside another one, so nothing is counted
+/// twice.
+///
+/// This is a knapsack over the directories in preorder, where the ones
+/// inside `order[i]` are exactly `order[i + 1..end[i]]`: deleting `order[i]`
+/// jumps straight to `end[i]`. The sums reachable before each position only
+/// grow along the order, so for each sum it is enough to remember the
+/// directory that first reached it; following those back gives the plan.
+/// Sums are only tracked below the single directory part two would delete,
+/// since a plan freeing that much or more can't be better, and only where
+/// the rest of the order can still make up the difference to `needed`. Sums
+/// of sizes form long runs, so they are kept as interval sets.
+fn plan_deletion(fs: &Filesystem, needed: u64) -> Option<Vec<NodeId>> {
+    if needed == 0 {
+        return Some(Vec::new());
+    }
+    let sizes = fs.sizes();
+    let order: Vec<_> = fs.walk(ROOT).into_iter().filter(|&id| fs.is_dir(id)).collect();
+    let index: HashMap<_, _> = order.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let mut end: Vec<_> = (1..=order.len()).collect();
+    for i in (0..order.len()).rev() {
+        if let Some(&parent) = fs.parent(order[i]).and_then(|parent| index.get(&parent)) {
+            end[parent] = end[parent].max(end[i]);
+        }
+    }
+    // The most that deleting directories from `order[i..]` can free.
+    let mut reach = vec![0; order.len() + 1];
+    for i in (0..order.len()).rev() {
+        reach[i] = sizes[order[i]] + reach[end[i]];
+    }
+
+    let single = order
+        .iter()
+        .copied()
+        .filter(|&dir| sizes[dir] >= needed)
+        .min_by_key(|&dir| sizes[dir]);
+    let limit = match single {
+        Some(dir) => sizes[dir],
+        None => return None,
+    };
+
+    let mut reachable: IntervalSet<u64> = [Interval::inclusive(0, 0).unwrap()].into_iter().collect();
+    // The pieces of the reachable sums along with the position of the
+    // directory that first made them reachable.
+    let mut via = Vec::new();
+    // For directories still being walked, the sums reachable before them,
+    // already shifted by their size.
+    let mut deleted: HashMap<usize, IntervalSet<u64>> = HashMap::new();
+    let mut ending: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..=order.len() {
+        for j in ending.remove(&i).unwrap_or_default() {
+            let sums = deleted.remove(&j).unwrap();
+            via.extend(reachable.merge(&sums).into_iter().map(|piece| (piece, j)));
+        }
+        if let (Some(&end), Some(&dir)) = (end.get(i), order.get(i)) {
+            if (1..limit).contains(&sizes[dir]) {
+                let from = needed.saturating_sub(reach[end]);
+                let within = Interval::new(from, limit).unwrap();
+                deleted.insert(i, reachable.shifted(sizes[dir], within));
+                ending.entry(end).or_default().push(i);
+            }
+        }
+    }
+
+    match reachable.first_from(needed).filter(|&sum| sum < limit) {
+        Some(mut sum) => {
+            let mut plan = Vec::new();
+            while sum > 0 {
+                let &(_, j) = via.iter().find(|(piece, _)| piece.contains(sum)).unwrap();
+                plan.push(order[j]);
+                sum -= sizes[order[j]];
+            }
+            plan.reverse();
+            Some(plan)
+        }
+        None => single.map(|dir| vec![dir]),
+    }
+}
+
+fn print_plan(fs: &Filesystem, model: &DiskModel) {
+    let needed = model.needed(fs);
+    let plan = plan_deletion(fs, needed)
+        .unwrap_or_else(|| panic!("deleting directories can't free {} more", needed));
+    let sizes = fs.sizes();
+    for &dir in &plan {
+        println!("delete {}\t{}", sizes[dir], fs.path(dir));
+    }
+    let freed: u64 = plan.iter().map(|&dir| sizes[dir]).sum();
+    println!(
+        "freed {} in {} directories, {} of {} free now ({} required)",
+        freed,
+        plan.len(),
+        model.free(fs) + freed,
+        model.disk_size,
+        model.required_free
+    );
+}
+
+#[derive(Clone, Copy)]
+enum SortBy {
+    Name,
+    /// Largest first, ties broken by name.
+    Size,
+}
+
+/// Renders the filesystem the way the puzzle description shows it, e.g.
+/// `- a (dir, size=94853)`, descending at most `max_depth` levels below the
+/// root.
+fn render_tree(fs: &Filesystem, sort: SortBy, max_depth: Option<usize>) -> String {
+    fn render(
+        fs: &Filesystem,
+        sizes: &[u64],
+        id: NodeId,
+        depth: usize,
+        sort: SortBy,
+        max_depth: Option<usize>,
+        out: &mut String,
+    ) {
+        let node = &fs.nodes[id];
+        let kind = if fs.is_dir(id) { "dir" } else { "file" };
+        out.push_str(&format!(
+            "{}- {} ({}, size={})\n",
+            "  ".repeat(depth),
+            node.name,
+            kind,
+            sizes[id]
+        ));
+
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return;
+        }
+        if let Some(children) = fs.children(id) {
+            let mut children: Vec<_> = children.values().copied().collect();
+            if let SortBy::Size = sort {
+                children.sort_by_key(|&child| std::cmp::Reverse(sizes[child]));
+            }
+            for child in children {
+                render(fs, sizes, child, depth + 1, sort, max_depth, out);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    render(fs, &fs.sizes(), ROOT, 0, sort, max_depth, &mut out);
+    out
+}
+
+/// Lists the `n` largest directories, like `du | sort -rn | head`.
+fn largest_dirs(fs: &Filesystem, n: usize) -> Vec<(u64, String)> {
+    let sizes = fs.sizes();
+    let mut dirs: Vec<_> = fs.dirs().map(|dir| (sizes[dir], fs.path(dir))).collect();
+    dirs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    dirs.truncate(n);
+    dirs
+}
+
+/// Whether `name` can be created inside a directory without ending up
+/// somewhere else, i.e. it is a single plain path component.
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+}
+
+/// Recreates the filesystem below `target`, with sparse files of the
+/// recorded sizes. Nothing that already exists is reused or overwritten, so
+/// symlinks planted in the target can't redirect the writes.
+fn export_dir(fs: &Filesystem, target: &Path) -> io::Result<()> {
+    fn export(fs: &Filesystem, dir: NodeId, path: &Path) -> io::Result<()> {
+        for (name, &child) in fs.children(dir).unwrap() {
+            if !is_plain_name(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("refusing to create {}", fs.path(child)),
+                ));
+            }
+            let child_path = path.join(name);
+            match fs.nodes[child].kind {
+                NodeKind::Dir { .. } => {
+                    fs::create_dir(&child_path)?;
+                    export(fs, child, &child_path)?;
+                }
+                NodeKind::File { size } => File::create_new(&child_path)?.set_len(size)?,
+            }
+        }
+        Ok(())
+    }
+
+    fs::create_dir_all(target)?;
+    export(fs, ROOT, target)
+}
+
+/// Serializes the tree below `id`. Directories carry their total size and
+/// their children, e.g.
+/// `{"name":"/","type":"dir","size":48381165,"children":[...]}`.
+fn format_json(fs: &Filesystem, sizes: &[u64], id: NodeId) -> String {
+    let node = &fs.nodes[id];
+    match fs.children(id) {
+        Some(children) => format!(
+            "{{\"name\":{},\"type\":\"dir\",\"size\":{},\"children\":[{}]}}",
+            json::string(&node.name),
+            sizes[id],
+            children
+                .values()
+                .map(|&child| format_json(fs, sizes, child))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        None => format!(
+            "{{\"name\":{},\"type\":\"file\",\"size\":{}}}",
+            json::string(&node.name),
+            sizes[id]
+        ),
+    }
+}
+
+/// Walks a real directory and writes the `$ cd`/`$ ls` transcript that would
+/// explore it, in a form `build_filesystem` reads back. Symlinks are left
+/// out, as are names the transcript format can't express.
+fn scan_dir<W: Write>(root: &Path, out: &mut W) -> io::Result<()> {
+    fn scan<W: Write>(path: &Path, out: &mut W) -> io::Result<()> {
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if !name.contains(char::is_whitespace) && is_plain_name(&name) => name,
+                _ => {
+                    eprintln!("skipping {}", entry.path().display());
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                dirs.push(name);
+            } else if metadata.is_file() {
+                files.push((name, metadata.len()));
+            }
+        }
+        dirs.sort();
+        files.sort();
+
+        writeln!(out, "$ ls")?;
+        for name in &dirs {
+            writeln!(out, "dir {}", name)?;
+        }
+        for (name, size) in &files {
+            writeln!(out, "{} {}", size, name)?;
+        }
+        for name in &dirs {
+            writeln!(out, "$ cd {}", name)?;
+            scan(&path.join(name), out)?;
+            writeln!(out, "$ cd ..")?;
+        }
+        Ok(())
+    }
+
+    writeln!(out, "$ cd /")?;
+    scan(root, out)
+}
+
+/// Runs one shell command against the filesystem, moving `cwd` on `cd`.
+fn run_shell_command(
+    fs: &Filesystem,
+    sizes: &[u64],
+    cwd: &mut NodeId,
+    command: &[&str],
+) -> Result<String, String> {
+    let mut out = String::new();
+    match command {
+        ["pwd"] => out.push_str(&format!("{}\n", fs.path(*cwd))),
+        ["cd"] => *cwd = ROOT,
+        ["cd", path] => {
+            let dir = fs.resolve(*cwd, path)?;
+            if !fs.is_dir(dir) {
+                return Err(format!("{}: not a directory", path));
+            }
+            *cwd = dir;
+        }
+        ["ls", path @ ..] if path.len() <= 1 => {
+            let id = path.first().map_or(Ok(*cwd), |path| fs.resolve(*cwd, path))?;
+            match fs.children(id) {
+                Some(children) => {
+                    for (name, &child) in children {
+                        match fs.nodes[child].kind {
+                            NodeKind::Dir { .. } => out.push_str(&format!("dir {}\n", name)),
+                            NodeKind::File { size } => out.push_str(&format!("{} {}\n", size, name)),
+                        }
+                    }
+                }
+                None => out.push_str(&format!("{} {}\n", sizes[id], fs.nodes[id].name)),
+            }
+        }
+        ["du", path @ ..] if path.len() <= 1 => {
+            let id = path.first().map_or(Ok(*cwd), |path| fs.resolve(*cwd, path))?;
+            for dir in fs.walk(id).into_iter().rev().filter(|&dir| fs.is_dir(dir)) {
+                out.push_str(&format!("{}\t{}\n", sizes[dir], fs.path(dir)));
+            }
+        }
+        ["find", rest @ ..] => {
+            let (path, threshold) = match rest {
+                ["-size", n] => (".", n),
+                [path, "-size", n] => (*path, n),
+                _ => return Err("usage: find [PATH] -size +N".to_string()),
+            };
+            let threshold: u64 = threshold
+                .strip_prefix('+')
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| format!("expected a size like +N, found {}", threshold))?;
+            let id = fs.resolve(*cwd, path)?;
+            for node in fs.walk(id).into_iter().filter(|&node| sizes[node] > threshold) {
+                out.push_str(&format!("{}\t{}\n", sizes[node], fs.path(node)));
+            }
+        }
+        [] => (),
+        [command, ..] => return Err(format!("{}: unknown command", command)),
+    }
+    Ok(out)
+}
+
+/// Reads shell commands from stdin until `exit` or the end of input.
+fn shell(fs: &Filesystem) -> io::Result<()> {
+    let sizes = fs.sizes();
+    let mut cwd = ROOT;
+    let mut stdout = io::stdout().lock();
+    let mut lines = io::stdin().lines();
+    loop {
+        write!(stdout, "{}$ ", fs.path(cwd))?;
+        stdout.flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let command: Vec<_> = line.split_whitespace().collect();
+        if command == ["exit"] {
+            break;
+        }
+        match run_shell_command(fs, &sizes, &mut cwd, &command) {
+            Ok(out) => write!(stdout, "{}", out)?,
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    writeln!(stdout)
+}
+
 fn main() {
-    let dir_sizes = compute_dir_sizes();
-    println!("{}", part_one(&dir_sizes));
-    println!("{}", part_two(&dir_sizes));
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let parse = |n: &str| -> usize {
+        n.parse().unwrap_or_else(|_| panic!("expected a number, found {}", n))
+    };
+
+    match args.as_slice() {
+        [] => {
+            let fs = read_filesystem();
+            println!("{}", part_one(&fs));
+            println!("{}", part_two(&fs, &DiskModel::default()));
+        }
+        ["tree", rest @ ..] => {
+            let mut sort = SortBy::Name;
+            let mut max_depth = None;
+            let mut rest = rest;
+            while !rest.is_empty() {
+                rest = match rest {
+                    ["--sort", "name", tail @ ..] => {
+                        sort = SortBy::Name;
+                        tail
+                    }
+                    ["--sort", "size", tail @ ..] => {
+                        sort = SortBy::Size;
+                        tail
+                    }
+                    ["--depth", n, tail @ ..] => {
+                        max_depth = Some(parse(n));
+                        tail
+                    }
+                    _ => panic!("usage: day07 tree [--sort name|size] [--depth N]"),
+                };
+            }
+            print!("{}", render_tree(&read_filesystem(), sort, max_depth));
+        }
+        ["plan", rest @ ..] => {
+            let parse_size = |n: &str| -> u64 {
+                n.parse().unwrap_or_else(|_| panic!("expected a size, found {}", n))
+            };
+            let mut model = DiskModel::default();
+            let mut rest = rest;
+            while !rest.is_empty() {
+                rest = match rest {
+                    ["--disk", n, tail @ ..] => {
+                        model.disk_size = parse_size(n);
+                        tail
+                    }
+                    ["--need", n, tail @ ..] => {
+                        model.required_free = parse_size(n);
+                        tail
+                    }
+                    _ => panic!("usage: day07 plan [--disk SIZE] [--need SIZE]"),
+                };
+            }
+            print_plan(&read_filesystem(), &model);
+        }
+        ["export-dir", target] => {
+            export_dir(&read_filesystem(), Path::new(target)).expect("failed to export");
+        }
+        ["export-json"] => {
+            let fs = read_filesystem();
+            println!("{}", format_json(&fs, &fs.sizes(), ROOT));
+        }
+        ["scan", dir] => {
+            let mut out = io::BufWriter::new(io::stdout().lock());
+            scan_dir(Path::new(dir), &mut out).expect("failed to scan");
+        }
+        ["shell", transcript] => {
+            let transcript = fs::read_to_string(transcript)
+                .unwrap_or_else(|e| panic!("failed to read {}: {}", transcript, e));
+            let fs = build_filesystem(transcript.lines().map(str::to_string))
+                .unwrap_or_else(|e| panic!("invalid transcript: {}", e));
+            shell(&fs).expect("failed to run shell");
+        }
+        ["validate"] => {
+            let (_, issues) = replay(read_lines());
+            if issues.is_empty() {
+                println!("no issues found");
+            }
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if issues.iter().any(|issue| issue.fatal) {
+                std::process::exit(1);
+            }
+        }
+        ["du", rest @ ..] => {
+            let n = match rest {
+                [] => 10,
+                [n] => parse(n),
+                _ => panic!("usage: day07 du [N]"),
+            };
+            for (size, path) in largest_dirs(&read_filesystem(), n) {
+                println!("{}\t{}", size, path);
+            }
+        }
+        _ => panic!(
+            "usage: day07 [tree [--sort name|size] [--depth N] | du [N] \
+             | plan [--disk SIZE] [--need SIZE] | validate \
+             | export-dir TARGET | export-json | scan DIR | shell TRANSCRIPT]"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k";
+
+    fn example() -> Filesystem {
+        build_filesystem(EXAMPLE.lines().map(str::to_string)).unwrap()
+    }
+
+    /// Every node's path and size, in preorder.
+    fn listing(fs: &Filesystem) -> Vec<(String, u64)> {
+        let sizes = fs.sizes();
+        fs.walk(ROOT).into_iter().map(|id| (fs.path(id), sizes[id])).collect()
+    }
+
+    /// A fresh directory under the system temp directory, removed on drop.
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("day07-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn example_parts() {
+        let fs = example();
+        assert_eq!(part_one(&fs), 95437);
+        assert_eq!(part_two(&fs, &DiskModel::default()), 24933642);
+    }
+
+    #[test]
+    fn example_plan_deletes_d() {
+        let fs = example();
+        let needed = DiskModel::default().needed(&fs);
+        let plan = plan_deletion(&fs, needed).unwrap();
+        let paths: Vec<_> = plan.iter().map(|&dir| fs.path(dir)).collect();
+        assert_eq!(paths, ["/d"]);
+        assert_eq!(fs.size(plan[0]), 24933642);
+
+        // With 49000000 required free, only deleting everything will do.
+        let model = DiskModel {
+            required_free: 49_000_000,
+            ..DiskModel::default()
+        };
+        assert_eq!(plan_deletion(&fs, model.needed(&fs)), Some(vec![ROOT]));
+        assert_eq!(plan_deletion(&fs, fs.size(ROOT) + 1), None);
+    }
+
+    #[test]
+    fn plan_combines_directories_without_nesting() {
+        let transcript = "\
+$ cd /
+$ ls
+dir x
+dir y
+dir z
+$ cd x
+$ ls
+50 a
+$ cd ../y
+$ ls
+60 b
+dir w
+$ cd w
+$ ls
+5 c
+$ cd /z
+$ ls
+100 d";
+        let fs = build_filesystem(transcript.lines().map(str::to_string)).unwrap();
+        let plan = |needed| {
+            plan_deletion(&fs, needed)
+                .map(|plan| plan.iter().map(|&dir| fs.path(dir)).collect::<Vec<_>>())
+        };
+        assert_eq!(plan(0).unwrap(), Vec::<String>::new());
+        assert_eq!(plan(51).unwrap(), ["/x", "/y/w"]);
+        assert_eq!(plan(105).unwrap(), ["/y/w", "/z"]);
+        assert_eq!(plan(111).unwrap(), ["/x", "/y"]);
+        assert_eq!(plan(160).unwrap(), ["/y", "/z"]);
+        // Deleting /x, /y and /z frees no less than / itself.
+        assert_eq!(plan(166).unwrap(), ["/"]);
+        assert_eq!(plan(215).unwrap(), ["/"]);
+        assert_eq!(plan(216), None);
+    }
+
+    #[test]
+    fn scan_reads_back_an_export() {
+        let tmp = TempDir::new("scan");
+        let target = tmp.0.join("example");
+        let fs = example();
+        export_dir(&fs, &target).unwrap();
+
+        let mut transcript = Vec::new();
+        scan_dir(&target, &mut transcript).unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        let scanned = build_filesystem(transcript.lines().map(str::to_string)).unwrap();
+        assert_eq!(listing(&scanned), listing(&fs));
+    }
+
+    #[test]
+    fn export_refuses_names_leaving_the_target() {
+        for entry in ["dir ..", "5 ..", "5 a/b", "dir a/b", "5 /etc"] {
+            let tmp = TempDir::new("refuse");
+            let target = tmp.0.join("target");
+            let transcript = format!("$ cd /\n$ ls\n{}", entry);
+            let fs = build_filesystem(transcript.lines().map(str::to_string)).unwrap();
+
+            let error = export_dir(&fs, &target).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", entry);
+            assert_eq!(fs::read_dir(&target).unwrap().count(), 0, "{}", entry);
+            assert_eq!(fs::read_dir(&tmp.0).unwrap().count(), 1, "{}", entry);
+        }
+    }
+
+    #[test]
+    fn shell_cd_cannot_go_above_root() {
+        let fs = example();
+        let sizes = fs.sizes();
+        let mut cwd = fs.resolve(ROOT, "/a").unwrap();
+        let mut run = |command: &str| {
+            let command: Vec<_> = command.split_whitespace().collect();
+            run_shell_command(&fs, &sizes, &mut cwd, &command)
+        };
+        assert_eq!(run("cd ../.."), Err("../..: cannot go above /".to_string()));
+        assert_eq!(run("pwd"), Ok("/a\n".to_string()));
+        assert!(run("ls /..").is_err());
+        assert_eq!(run("cd ../d/../a/e"), Ok(String::new()));
+        assert_eq!(run("pwd"), Ok("/a/e\n".to_string()));
+
+        let transcript = "$ cd /\n$ cd ..";
+        let error = build_filesystem(transcript.lines().map(str::to_string)).err().unwrap();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn replay_reports_each_issue_once() {
+        let transcript = "\
+$ cd /
+$ ls
+dir a
+5 b
+
+$ cd c
+$ ls
+7 d
+7 d
+8 d
+$ cd /
+$ ls -la
+dir b
+9 a
+$ cd a
+10 e
+$ pwd
+x y
+$ cd ../../..";
+        let (_, issues) = replay(transcript.lines().map(str::to_string));
+        let issues: Vec<_> = issues.iter().map(|issue| issue.to_string()).collect();
+        assert_eq!(
+            issues,
+            [
+                "line 5: warning: blank line",
+                "line 6: warning: cd into /c, which was never listed",
+                "line 10: warning: /c/d was listed with size 7 before, now 8",
+                "line 12: warning: ls with unsupported arguments: -la",
+                "line 13: error: /b is a file, not a directory",
+                "line 14: error: /a is a directory, not a file",
+                "line 16: warning: output outside of an ls",
+                "line 17: error: unknown command: pwd",
+                "line 18: warning: output outside of an ls",
+                "line 18: error: failed to parse size: x",
+                "line 19: error: cannot go above / with cd ../../..",
+            ]
+        );
+    }
+}
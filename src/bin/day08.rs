@@ -112,16 +112,21 @@ fn part_one(forest: &Grid<u8>) -> usize {
 
     assert_eq!(forest.w(), visible.w());
 
-    for i in 0..forest.h() {
+    let (w, h) = (forest.w(), forest.h());
+    for i in 0..h {
         let mut lr = TreeHeightMeasurer::new();
         let mut rl = TreeHeightMeasurer::new();
+        for j in 0..w {
+            lr.measure_and_update(forest[(i, j)], &mut visible[(i, j)]);
+            rl.measure_and_update(forest[(i, w - j - 1)], &mut visible[(i, w - j - 1)]);
+        }
+    }
+    for j in 0..w {
         let mut tb = TreeHeightMeasurer::new();
         let mut bt = TreeHeightMeasurer::new();
-        for j in 0..forest.w() {
-            lr.measure_and_update(forest[(i, j)], &mut visible[(i, j)]);
-            rl.measure_and_update(forest[(i, forest.w() - j - 1)], &mut visible[(i, forest.w() - j - 1)]);
-            tb.measure_and_update(forest[(j, i)], &mut visible[(j, i)]);
-            bt.measure_and_update(forest[(forest.h() - j - 1, i)], &mut visible[(forest.h() - j - 1, i)]);
+        for i in 0..h {
+            tb.measure_and_update(forest[(i, j)], &mut visible[(i, j)]);
+            bt.measure_and_update(forest[(h - i - 1, j)], &mut visible[(h - i - 1, j)]);
         }
     }
 
@@ -139,4 +144,35 @@ fn part_one(forest: &Grid<u8>) -> usize {
 fn main() {
     let grid = read_input();
     println!("{}", part_one(&grid));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forest(rows: &[&str]) -> Grid<u8> {
+        let rows: Vec<Vec<u8>> = rows.iter().map(|row| row.bytes().collect()).collect();
+        Grid::from_rows(&rows)
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part_one(&forest(&["30373", "25512", "65332", "33549", "35390"])), 21);
+    }
+
+    #[test]
+    fn wide_forest() {
+        // 12 trees on the edge, plus the two 9s inside.
+        assert_eq!(part_one(&forest(&["11111", "19191", "11111"])), 14);
+        assert_eq!(part_one(&forest(&["30373", "25512"])), 10);
+    }
+
+    #[test]
+    fn tall_forest() {
+        // 12 trees on the edge; inside only the 5s in the second and third
+        // row are visible, from the left and from the right.
+        assert_eq!(part_one(&forest(&["303", "255", "653", "335", "353"])), 14);
+        assert_eq!(part_one(&forest(&["111", "191", "111", "191", "111"])), 14);
+        assert_eq!(part_one(&forest(&["1", "2", "3"])), 3);
+    }
+}
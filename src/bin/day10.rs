@@ -44,7 +44,7 @@ fn compute_x_state(instructions: &[Instruction]) -> Vec<i64> {
 
 fn part_one(instructions: &[Instruction]) -> i64 {
     let x_state = compute_x_state(instructions);
-    let points = vec![20, 60, 100, 140, 180, 220];
+    let points = [20, 60, 100, 140, 180, 220];
     points.iter()
         .map(|&p| x_state[p - 1] * p as i64)
         .sum()
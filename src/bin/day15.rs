@@ -1,3 +1,4 @@
+use advent_of_code_2022::interval::{Interval, IntervalSet};
 use regex::Regex;
 use std::io;
 
@@ -8,16 +9,10 @@ struct Point {
 
 impl Point {
     fn manhattan_distance(&self, other: &Self) -> u32 {
-        (self.x - other.x).abs() as u32 + (self.y - other.y).abs() as u32
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
     }
 }
 
-#[derive(Clone)]
-struct Interval {
-    start: i32,
-    end: i32,
-}
-
 struct Sensor {
     location: Point,
     nearest_beacon: Point,
@@ -59,44 +54,27 @@ fn read_input() -> Vec<Sensor> {
         .collect()
 }
 
-fn merge_intervals(intervals: &[Interval]) -> Vec<Interval> {
-    let mut intervals = intervals.to_vec();
-    intervals.sort_by_key(|i| i.start);
-
-    let mut merged = vec![intervals[0].clone()];
-    for int in intervals.iter().skip(1) {
-        let last = merged.last_mut().unwrap();
-        if int.start <= last.end {
-            last.end = last.end.max(int.end);
-        } else {
-            merged.push(int.clone());
-        }
-    }
-    merged
-}
-
 fn part_one(sensors: &[Sensor]) -> u32 {
     const ROW: i32 = 2000000;
 
-    let mut intervals: Vec<_> = sensors
+    let covered: IntervalSet<i32> = sensors
         .iter()
         .filter_map(|s| {
             let min_range = s.min_range();
-            let y_distance = (s.location.y - ROW).abs() as u32;
+            let y_distance = s.location.y.abs_diff(ROW);
             if y_distance > min_range {
                 None
             } else {
                 let x_range = (min_range - y_distance) as i32;
-                Some(Interval {
-                    start: s.location.x - x_range,
-                    end: s.location.x + x_range,
-                })
+                Some(
+                    Interval::inclusive(s.location.x - x_range, s.location.x + x_range)
+                        .expect("sensor range is out of bounds"),
+                )
             }
         })
         .collect();
 
-    let merged = merge_intervals(&intervals);
-    merged.iter().map(|i| (i.end - i.start) as u32 + 1).sum()
+    covered.len() as u32
 }
 
 fn main() {
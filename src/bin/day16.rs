@@ -16,7 +16,7 @@ fn parse_valve(line: &str) -> Valve {
     let re =
         Regex::new(r"Valve ([A-Z]{2}) has flow rate=(\d+); tunnels? leads? to valves? ([A-Z]{2}(?:, [A-Z]{2})*)")
             .unwrap();
-    let caps = re.captures(&line).expect("cannot parse input");
+    let caps = re.captures(line).expect("cannot parse input");
     let get_match = |i| caps.get(i).unwrap().as_str();
 
     let name = get_match(1).to_string();
@@ -0,0 +1,470 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// An ordered type whose values can be enumerated one by one, such as
+/// section ids or grid coordinates.
+pub trait Discrete: Copy + Ord {
+    fn succ(self) -> Option<Self>;
+    fn pred(self) -> Option<Self>;
+    /// The number of values in `[from, to)`, `to` must not be below `from`.
+    fn distance(from: Self, to: Self) -> u64;
+}
+
+macro_rules! impl_discrete {
+    ($($t:ty),*) => {
+        $(
+            impl Discrete for $t {
+                fn succ(self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn pred(self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+
+                fn distance(from: Self, to: Self) -> u64 {
+                    (to as i128 - from as i128) as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_discrete!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IntervalError {
+    /// The end of the interval comes before its start, e.g. `5-3`.
+    Reversed,
+    /// The inclusive end is the largest value of the type, so the interval
+    /// cannot be represented as a half-open one.
+    Overflow,
+    /// The text is not of the form `first-last`.
+    Format,
+}
+
+impl fmt::Display for IntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntervalError::Reversed => write!(f, "interval end is before its start"),
+            IntervalError::Overflow => write!(f, "interval end is out of range"),
+            IntervalError::Format => write!(f, "expected an interval of the form first-last"),
+        }
+    }
+}
+
+/// A half-open interval `[start, end)`. An interval with `start == end` is
+/// empty; a reversed interval cannot be constructed.
+#[derive(Debug, Clone, Copy)]
+pub struct Interval<T> {
+    start: T,
+    end: T,
+}
+
+impl<T: Discrete> Interval<T> {
+    /// Creates the interval `[start, end)`.
+    pub fn new(start: T, end: T) -> Result<Self, IntervalError> {
+        if end < start {
+            Err(IntervalError::Reversed)
+        } else {
+            Ok(Interval { start, end })
+        }
+    }
+
+    /// Creates the interval `[first, last]`.
+    pub fn inclusive(first: T, last: T) -> Result<Self, IntervalError> {
+        if last < first {
+            return Err(IntervalError::Reversed);
+        }
+        let end = last.succ().ok_or(IntervalError::Overflow)?;
+        Ok(Interval { start: first, end })
+    }
+
+    pub fn empty(at: T) -> Self {
+        Interval { start: at, end: at }
+    }
+
+    pub fn start(&self) -> T {
+        self.start
+    }
+
+    /// The exclusive end of the interval.
+    pub fn end(&self) -> T {
+        self.end
+    }
+
+    /// The largest value in the interval, `None` if it is empty.
+    pub fn last(&self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            self.end.pred()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> u64 {
+        T::distance(self.start, self.end)
+    }
+
+    pub fn contains(&self, x: T) -> bool {
+        self.start <= x && x < self.end
+    }
+
+    /// Whether every value of `self` is also in `other`. The empty interval
+    /// is a subset of any interval.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.is_empty() || (other.start <= self.start && self.end <= other.end)
+    }
+
+    /// Whether the intervals have at least one value in common.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        if end < start {
+            Interval::empty(start)
+        } else {
+            Interval { start, end }
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> IntervalSet<T> {
+        [*self, *other].into_iter().collect()
+    }
+
+    pub fn difference(&self, other: &Self) -> IntervalSet<T> {
+        if other.is_empty() {
+            return [*self].into_iter().collect();
+        }
+        let left = Interval {
+            start: self.start,
+            end: self.end.min(other.start).max(self.start),
+        };
+        let right = Interval {
+            start: self.start.max(other.end).min(self.end),
+            end: self.end,
+        };
+        [left, right].into_iter().collect()
+    }
+}
+
+impl<T: Discrete> PartialEq for Interval<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.is_empty() && other.is_empty()) || (self.start == other.start && self.end == other.end)
+    }
+}
+
+impl<T: Discrete> Eq for Interval<T> {}
+
+/// Formats the interval as `first-last`, the way it is written in puzzle inputs.
+impl<T: Discrete + fmt::Display> fmt::Display for Interval<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.last() {
+            Some(last) => write!(f, "{}-{}", self.start, last),
+            None => write!(f, "empty"),
+        }
+    }
+}
+
+/// Parses an inclusive interval written as `first-last`, e.g. `2-4` or `-5--3`.
+impl<T: Discrete + FromStr> FromStr for Interval<T> {
+    type Err = IntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Skip the first character so that a leading minus sign isn't taken
+        // for the separator.
+        let sep = s
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c == '-')
+            .map(|(i, _)| i)
+            .ok_or(IntervalError::Format)?;
+        let (first, last) = (&s[..sep], &s[sep + 1..]);
+        match (first.parse(), last.parse()) {
+            (Ok(first), Ok(last)) => Interval::inclusive(first, last),
+            _ => Err(IntervalError::Format),
+        }
+    }
+}
+
+/// A set of values kept as sorted, disjoint and non-adjacent intervals.
+#[derive(Debug, Clone)]
+pub struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+impl<T: Discrete> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet { intervals: Vec::new() }
+    }
+
+    pub fn insert(&mut self, interval: Interval<T>) {
+        if interval.is_empty() {
+            return;
+        }
+        // Intervals in [lo, hi) overlap or touch the new one.
+        let lo = self.intervals.partition_point(|i| i.end < interval.start);
+        let hi = self.intervals.partition_point(|i| i.start <= interval.end);
+        let mut merged = interval;
+        if lo < hi {
+            merged.start = merged.start.min(self.intervals[lo].start);
+            merged.end = merged.end.max(self.intervals[hi - 1].end);
+        }
+        self.intervals.splice(lo..hi, [merged]);
+    }
+
+    pub fn intervals(&self) -> &[Interval<T>] {
+        &self.intervals
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Interval<T>> {
+        self.intervals.iter()
+    }
+
+    /// The total number of values in the set.
+    pub fn len(&self) -> u64 {
+        self.intervals.iter().map(Interval::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn contains(&self, x: T) -> bool {
+        let i = self.intervals.partition_point(|i| i.end <= x);
+        self.intervals.get(i).is_some_and(|i| i.contains(x))
+    }
+
+    /// The smallest value in the set that is at least `x`.
+    pub fn first_from(&self, x: T) -> Option<T> {
+        let i = self.intervals.partition_point(|i| i.end <= x);
+        self.intervals.get(i).map(|i| i.start.max(x))
+    }
+
+    /// Adds every value of `other` and returns the pieces of `other` that
+    /// weren't in the set yet, in order.
+    pub fn merge(&mut self, other: &IntervalSet<T>) -> Vec<Interval<T>> {
+        let mut added = Vec::new();
+        let mut next = 0;
+        for interval in &other.intervals {
+            let mut at = interval.start;
+            while at < interval.end {
+                while self.intervals.get(next).is_some_and(|i| i.end <= at) {
+                    next += 1;
+                }
+                match self.intervals.get(next) {
+                    Some(i) if i.start <= at => at = i.end,
+                    i => {
+                        let end = i.map_or(interval.end, |i| i.start.min(interval.end));
+                        added.push(Interval { start: at, end });
+                        at = end;
+                    }
+                }
+            }
+        }
+
+        let mut merged: Vec<Interval<T>> = Vec::with_capacity(self.intervals.len() + added.len());
+        let mut old = self.intervals.iter().peekable();
+        let mut new = added.iter().peekable();
+        while let Some(&interval) = match (old.peek(), new.peek()) {
+            (Some(a), Some(b)) if a.start <= b.start => old.next(),
+            (Some(_), Some(_)) | (None, _) => new.next(),
+            (Some(_), None) => old.next(),
+        } {
+            match merged.last_mut() {
+                Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+                _ => merged.push(interval),
+            }
+        }
+        self.intervals = merged;
+        added
+    }
+}
+
+impl IntervalSet<u64> {
+    /// The values of the set plus `delta` that lie in `within`. Values that
+    /// would overflow are dropped.
+    pub fn shifted(&self, delta: u64, within: Interval<u64>) -> IntervalSet<u64> {
+        let first = self
+            .intervals
+            .partition_point(|i| i.end.saturating_add(delta) <= within.start);
+        let intervals = self.intervals[first..]
+            .iter()
+            .map_while(|i| {
+                let start = i.start.checked_add(delta)?.max(within.start);
+                let end = i.end.saturating_add(delta).min(within.end);
+                (start < end).then_some(Interval { start, end })
+            })
+            .collect();
+        IntervalSet { intervals }
+    }
+}
+
+impl<T: Discrete> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet::new()
+    }
+}
+
+impl<T: Discrete> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        let mut sorted: Vec<_> = iter.into_iter().filter(|i| !i.is_empty()).collect();
+        sorted.sort_by_key(|i| i.start);
+
+        let mut intervals: Vec<Interval<T>> = Vec::with_capacity(sorted.len());
+        for interval in sorted {
+            match intervals.last_mut() {
+                Some(last) if interval.start <= last.end => {
+                    last.end = last.end.max(interval.end);
+                }
+                _ => intervals.push(interval),
+            }
+        }
+        IntervalSet { intervals }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iv(s: &str) -> Interval<i32> {
+        s.parse().unwrap()
+    }
+
+    fn set(intervals: &[&str]) -> Vec<Interval<i32>> {
+        intervals.iter().map(|s| iv(s)).collect()
+    }
+
+    #[test]
+    fn empty_intervals() {
+        let empty = Interval::empty(7);
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.last(), None);
+        assert!(!empty.contains(7));
+        assert_eq!(empty, Interval::empty(-3));
+        assert_eq!(empty, Interval::new(2, 2).unwrap());
+        assert_eq!(empty.to_string(), "empty");
+
+        assert!(empty.is_subset(&iv("1-2")));
+        assert!(!empty.overlaps(&iv("0-10")));
+        assert!(iv("1-3").intersection(&iv("5-6")).is_empty());
+        assert!(empty.difference(&iv("1-3")).is_empty());
+        assert_eq!(iv("1-3").difference(&empty).intervals(), set(&["1-3"]));
+    }
+
+    #[test]
+    fn reversed_intervals_are_rejected() {
+        assert_eq!("5-3".parse::<Interval<i32>>(), Err(IntervalError::Reversed));
+        assert_eq!(Interval::new(5, 3), Err(IntervalError::Reversed));
+        assert_eq!(Interval::inclusive(5u32, 3), Err(IntervalError::Reversed));
+    }
+
+    #[test]
+    fn parsing() {
+        let negative = iv("-5--3");
+        assert_eq!((negative.start(), negative.end(), negative.len()), (-5, -2, 3));
+        assert_eq!(negative.to_string(), "-5--3");
+        assert_eq!(iv("-2-2").len(), 5);
+        assert_eq!(iv("4-4").last(), Some(4));
+
+        assert_eq!("255-255".parse::<Interval<u8>>(), Err(IntervalError::Overflow));
+        for bad in ["", "5", "-5", "a-b", "1-", "-1-", "1 - 2"] {
+            assert_eq!(bad.parse::<Interval<i32>>(), Err(IntervalError::Format), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn difference() {
+        // `other` inside `self` leaves both ends.
+        assert_eq!(iv("1-10").difference(&iv("4-6")).intervals(), set(&["1-3", "7-10"]));
+        // `other` covering `self` leaves nothing.
+        assert!(iv("4-6").difference(&iv("1-10")).is_empty());
+        assert!(iv("4-6").difference(&iv("4-6")).is_empty());
+        // Disjoint intervals leave `self` as it is.
+        assert_eq!(iv("1-3").difference(&iv("5-8")).intervals(), set(&["1-3"]));
+        assert_eq!(iv("5-8").difference(&iv("1-3")).intervals(), set(&["5-8"]));
+        // Partial overlaps cut one end.
+        assert_eq!(iv("1-5").difference(&iv("4-8")).intervals(), set(&["1-3"]));
+        assert_eq!(iv("4-8").difference(&iv("1-5")).intervals(), set(&["6-8"]));
+    }
+
+    #[test]
+    fn set_insert_merges() {
+        let mut s = IntervalSet::new();
+        s.insert(iv("1-3"));
+        s.insert(iv("7-9"));
+        assert_eq!(s.intervals(), set(&["1-3", "7-9"]));
+
+        // Adjacent ranges merge even without a common value.
+        s.insert(iv("4-4"));
+        assert_eq!(s.intervals(), set(&["1-4", "7-9"]));
+        s.insert(iv("5-6"));
+        assert_eq!(s.intervals(), set(&["1-9"]));
+
+        s.insert(Interval::empty(20));
+        s.insert(iv("11-12"));
+        s.insert(iv("-3--2"));
+        assert_eq!(s.intervals(), set(&["-3--2", "1-9", "11-12"]));
+        s.insert(iv("-5-15"));
+        assert_eq!(s.intervals(), set(&["-5-15"]));
+        assert_eq!(s.len(), 21);
+        assert!(s.contains(-5) && s.contains(15) && !s.contains(16));
+    }
+
+    #[test]
+    fn union() {
+        assert_eq!(iv("1-3").union(&iv("4-6")).intervals(), set(&["1-6"]));
+        assert_eq!(iv("1-3").union(&iv("5-6")).intervals(), set(&["1-3", "5-6"]));
+        assert_eq!(iv("1-10").union(&iv("2-3")).intervals(), set(&["1-10"]));
+    }
+
+    #[test]
+    fn merge_reports_added_pieces() {
+        let mut s: IntervalSet<i32> = set(&["1-3", "7-9"]).into_iter().collect();
+        let other = set(&["0-1", "3-8", "11-12"]).into_iter().collect();
+        assert_eq!(s.merge(&other), set(&["0-0", "4-6", "11-12"]));
+        assert_eq!(s.intervals(), set(&["0-9", "11-12"]));
+
+        // Merging values that are all there already adds nothing.
+        assert_eq!(s.merge(&set(&["2-5"]).into_iter().collect()), []);
+        assert_eq!(s.merge(&IntervalSet::new()), []);
+        // An added piece right next to an old interval joins it.
+        assert_eq!(s.merge(&set(&["10-10"]).into_iter().collect()), set(&["10-10"]));
+        assert_eq!(s.intervals(), set(&["0-12"]));
+    }
+
+    #[test]
+    fn first_from() {
+        let s: IntervalSet<i32> = set(&["1-3", "7-9"]).into_iter().collect();
+        assert_eq!(s.first_from(-4), Some(1));
+        assert_eq!(s.first_from(2), Some(2));
+        assert_eq!(s.first_from(4), Some(7));
+        assert_eq!(s.first_from(10), None);
+    }
+
+    #[test]
+    fn shifted_stays_within_and_drops_overflow() {
+        let s: IntervalSet<u64> = [Interval::new(0, 3).unwrap(), Interval::new(5, 8).unwrap()]
+            .into_iter()
+            .collect();
+        let within = |start, end| Interval::new(start, end).unwrap();
+        let shifted = s.shifted(10, within(11, 16));
+        assert_eq!(shifted.intervals(), [within(11, 13), within(15, 16)]);
+        assert!(s.shifted(10, within(13, 15)).is_empty());
+
+        let s: IntervalSet<u64> = [Interval::new(0, 1).unwrap(), Interval::new(5, u64::MAX).unwrap()]
+            .into_iter()
+            .collect();
+        let shifted = s.shifted(u64::MAX - 2, within(0, u64::MAX));
+        assert_eq!(shifted.intervals(), [within(u64::MAX - 2, u64::MAX - 1)]);
+    }
+}
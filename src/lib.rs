@@ -0,0 +1,4 @@
+pub mod interval;
+pub mod rope;
+pub mod marker;
+pub mod json;
@@ -0,0 +1,310 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// What counts as a marker: a window of `len` symbols with at least
+/// `min_distinct` different symbols in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarkerSpec {
+    pub len: usize,
+    pub min_distinct: usize,
+}
+
+impl MarkerSpec {
+    /// A window of `len` symbols that are all different.
+    pub fn unique(len: usize) -> Self {
+        MarkerSpec { len, min_distinct: len }
+    }
+
+    /// A window of `len` symbols where at most `max_repeats` symbols repeat
+    /// an earlier symbol of the window.
+    pub fn with_max_repeats(len: usize, max_repeats: usize) -> Self {
+        MarkerSpec {
+            len,
+            min_distinct: len.saturating_sub(max_repeats),
+        }
+    }
+
+    /// A window of `len` symbols with at least `min_distinct` different ones.
+    pub fn with_min_distinct(len: usize, min_distinct: usize) -> Self {
+        MarkerSpec { len, min_distinct }
+    }
+}
+
+/// Keeps count of the symbols in a window.
+pub trait SymbolCounter<S> {
+    fn add(&mut self, symbol: &S);
+    fn remove(&mut self, symbol: &S);
+    /// The number of different symbols with a non-zero count.
+    fn distinct(&self) -> usize;
+}
+
+/// Counts any hashable symbol, e.g. whole tokens.
+pub struct HashCounter<S> {
+    counts: HashMap<S, u32>,
+}
+
+impl<S> Default for HashCounter<S> {
+    fn default() -> Self {
+        HashCounter {
+            counts: HashMap::new(),
+        }
+    }
+}
+
+impl<S: Eq + Hash + Clone> SymbolCounter<S> for HashCounter<S> {
+    fn add(&mut self, symbol: &S) {
+        *self.counts.entry(symbol.clone()).or_insert(0) += 1;
+    }
+
+    fn remove(&mut self, symbol: &S) {
+        if let Some(count) = self.counts.get_mut(symbol) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(symbol);
+            }
+        }
+    }
+
+    fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+}
+
+/// Counts bytes in a table, with a 256-bit mask of the bytes present so
+/// that the number of distinct bytes is a popcount.
+pub struct ByteCounter {
+    counts: [u32; u8::MAX as usize + 1],
+    present: [u64; 4],
+}
+
+impl Default for ByteCounter {
+    fn default() -> Self {
+        ByteCounter {
+            counts: [0; u8::MAX as usize + 1],
+            present: [0; 4],
+        }
+    }
+}
+
+impl SymbolCounter<u8> for ByteCounter {
+    fn add(&mut self, &b: &u8) {
+        self.counts[b as usize] += 1;
+        if self.counts[b as usize] == 1 {
+            self.present[b as usize >> 6] |= 1 << (b & 63);
+        }
+    }
+
+    fn remove(&mut self, &b: &u8) {
+        self.counts[b as usize] -= 1;
+        if self.counts[b as usize] == 0 {
+            self.present[b as usize >> 6] &= !(1 << (b & 63));
+        }
+    }
+
+    fn distinct(&self) -> usize {
+        self.present.iter().map(|mask| mask.count_ones() as usize).sum()
+    }
+}
+
+/// Counts ASCII characters like bytes and everything else in a hash map.
+#[derive(Default)]
+pub struct CharCounter {
+    ascii: ByteCounter,
+    other: HashCounter<char>,
+}
+
+impl SymbolCounter<char> for CharCounter {
+    fn add(&mut self, &c: &char) {
+        if c.is_ascii() {
+            self.ascii.add(&(c as u8));
+        } else {
+            self.other.add(&c);
+        }
+    }
+
+    fn remove(&mut self, &c: &char) {
+        if c.is_ascii() {
+            self.ascii.remove(&(c as u8));
+        } else {
+            self.other.remove(&c);
+        }
+    }
+
+    fn distinct(&self) -> usize {
+        self.ascii.distinct() + self.other.distinct()
+    }
+}
+
+/// Looks for markers in a signal that is fed one symbol at a time, so the
+/// signal never has to be in memory as a whole. Every symbol takes O(1).
+pub struct MarkerScanner<S, C = HashCounter<S>> {
+    spec: MarkerSpec,
+    window: VecDeque<S>,
+    counter: C,
+    position: usize,
+}
+
+pub type ByteMarkerScanner = MarkerScanner<u8, ByteCounter>;
+pub type CharMarkerScanner = MarkerScanner<char, CharCounter>;
+
+impl<S, C> MarkerScanner<S, C>
+where
+    C: SymbolCounter<S> + Default,
+{
+    pub fn new(spec: MarkerSpec) -> Self {
+        MarkerScanner {
+            spec,
+            window: VecDeque::with_capacity(spec.len + 1),
+            counter: C::default(),
+            position: 0,
+        }
+    }
+
+    /// Feeds the next symbol. Returns the number of symbols processed so far
+    /// if they end with a marker.
+    pub fn push(&mut self, symbol: S) -> Option<usize> {
+        self.position += 1;
+        self.counter.add(&symbol);
+        self.window.push_back(symbol);
+
+        if self.window.len() > self.spec.len {
+            let old = self.window.pop_front().unwrap();
+            self.counter.remove(&old);
+        }
+
+        if self.window.len() == self.spec.len && self.counter.distinct() >= self.spec.min_distinct {
+            Some(self.position)
+        } else {
+            None
+        }
+    }
+
+    /// Starts over with a new signal.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.counter = C::default();
+        self.position = 0;
+    }
+}
+
+/// Returns the number of symbols up to and including the first marker.
+pub fn find_marker<S, I>(symbols: I, spec: MarkerSpec) -> Option<usize>
+where
+    S: Eq + Hash + Clone,
+    I: IntoIterator<Item = S>,
+{
+    let mut scanner: MarkerScanner<S> = MarkerScanner::new(spec);
+    symbols.into_iter().find_map(|s| scanner.push(s))
+}
+
+/// Like `find_marker`, specialized for bytes.
+pub fn find_byte_marker(signal: &[u8], spec: MarkerSpec) -> Option<usize> {
+    let mut scanner = ByteMarkerScanner::new(spec);
+    signal.iter().find_map(|&b| scanner.push(b))
+}
+
+/// Like `find_marker`, specialized for the characters of a string.
+pub fn find_char_marker(signal: &str, spec: MarkerSpec) -> Option<usize> {
+    let mut scanner = CharMarkerScanner::new(spec);
+    signal.chars().find_map(|c| scanner.push(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_markers(signal: &str, spec: MarkerSpec) -> Vec<usize> {
+        let mut scanner = CharMarkerScanner::new(spec);
+        signal.chars().filter_map(|c| scanner.push(c)).collect()
+    }
+
+    #[test]
+    fn counters_track_distinct_symbols() {
+        let mut bytes = ByteCounter::default();
+        let mut chars = CharCounter::default();
+        let mut hashed = HashCounter::default();
+        for (i, c) in "abαab€".chars().enumerate() {
+            chars.add(&c);
+            hashed.add(&c);
+            if c.is_ascii() {
+                bytes.add(&(c as u8));
+            }
+            assert_eq!(chars.distinct(), hashed.distinct(), "after {} symbols", i + 1);
+        }
+        assert_eq!((bytes.distinct(), chars.distinct()), (2, 4));
+
+        for c in ['a', 'α', 'b', 'a'] {
+            chars.remove(&c);
+            hashed.remove(&c);
+            if c.is_ascii() {
+                bytes.remove(&(c as u8));
+            }
+        }
+        assert_eq!((bytes.distinct(), chars.distinct(), hashed.distinct()), (1, 2, 2));
+
+        // Bytes at both ends of the table land in different mask words.
+        let mut edges = ByteCounter::default();
+        for b in [0, 63, 64, 255, 255] {
+            edges.add(&b);
+        }
+        assert_eq!(edges.distinct(), 4);
+        edges.remove(&255);
+        assert_eq!(edges.distinct(), 4);
+        edges.remove(&255);
+        assert_eq!(edges.distinct(), 3);
+    }
+
+    #[test]
+    fn search_paths_agree_on_ascii() {
+        let signals = [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "bvwbjplbgvbhsrlpgdmjqwftvncz",
+            "nppdvjthqldpwncqszvftbrmjlhg",
+            "nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+            "zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+            "aaaa",
+            "",
+        ];
+        for signal in signals {
+            for len in [1, 4, 14] {
+                let spec = MarkerSpec::unique(len);
+                let expected = find_marker(signal.bytes(), spec);
+                assert_eq!(find_byte_marker(signal.as_bytes(), spec), expected, "{}", signal);
+                assert_eq!(find_char_marker(signal, spec), expected, "{}", signal);
+                assert_eq!(find_marker(signal.chars(), spec), expected, "{}", signal);
+            }
+        }
+        assert_eq!(find_byte_marker(signals[0].as_bytes(), MarkerSpec::unique(4)), Some(7));
+        assert_eq!(find_byte_marker(signals[0].as_bytes(), MarkerSpec::unique(14)), Some(19));
+    }
+
+    #[test]
+    fn multi_byte_chars_are_one_symbol() {
+        let spec = MarkerSpec::unique(4);
+        let signal = "ααβγδ";
+        assert_eq!(find_byte_marker(signal.as_bytes(), spec), None);
+        assert_eq!(find_char_marker(signal, spec), Some(5));
+        assert_eq!(find_marker(signal.chars(), spec), Some(5));
+
+        let tokens = ["up", "up", "down", "left", "right"];
+        assert_eq!(find_marker(tokens, spec), Some(5));
+    }
+
+    #[test]
+    fn approximate_markers() {
+        // At most one repeat means at least 3 distinct symbols out of 4.
+        let spec = MarkerSpec::with_max_repeats(4, 1);
+        assert_eq!(spec, MarkerSpec::with_min_distinct(4, 3));
+        assert_eq!(all_markers("aabbccdd", spec), [5, 7]);
+        assert_eq!(find_marker("aabbccdd".chars(), spec), Some(5));
+
+        assert_eq!(all_markers("aabbccdd", MarkerSpec::with_max_repeats(4, 0)), []);
+        assert_eq!(all_markers("aabb", MarkerSpec::with_max_repeats(4, 2)), [4]);
+        // Allowing more repeats than the window has symbols accepts any window.
+        assert_eq!(all_markers("aaaaa", MarkerSpec::with_max_repeats(4, 9)), [4, 5]);
+
+        // No window of 4 holds 5 distinct symbols.
+        assert_eq!(all_markers("abcdefgh", MarkerSpec::with_min_distinct(4, 5)), []);
+        assert_eq!(find_byte_marker(b"abcdefgh", MarkerSpec::with_min_distinct(4, 5)), None);
+    }
+}
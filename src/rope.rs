@@ -0,0 +1,360 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A sequence backed by an implicit treap. Splitting, appending and
+/// reversing take O(log n) expected time, which makes it a good fit for
+/// moving long runs of elements between sequences.
+pub struct Rope<T> {
+    root: Link<T>,
+}
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    value: T,
+    priority: u64,
+    size: usize,
+    /// The subtree below this node, this node included, has to be read in
+    /// reverse order. Applied lazily by `push_down`.
+    reversed: bool,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+fn next_priority() -> u64 {
+    // splitmix64 over a global counter gives well spread priorities without
+    // pulling in a random number generator.
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut z = COUNTER
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+impl<T> Node<T> {
+    fn new(value: T) -> Box<Self> {
+        Box::new(Node {
+            value,
+            priority: next_priority(),
+            size: 1,
+            reversed: false,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    fn push_down(&mut self) {
+        if self.reversed {
+            std::mem::swap(&mut self.left, &mut self.right);
+            for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+                child.reversed = !child.reversed;
+            }
+            self.reversed = false;
+        }
+    }
+}
+
+/// Splits off the first `k` elements.
+fn split<T>(link: Link<T>, k: usize) -> (Link<T>, Link<T>) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            node.push_down();
+            let left_size = size(&node.left);
+            if k <= left_size {
+                let (l, r) = split(node.left.take(), k);
+                node.left = r;
+                node.update();
+                (l, Some(node))
+            } else {
+                let (l, r) = split(node.right.take(), k - left_size - 1);
+                node.right = l;
+                node.update();
+                (Some(node), r)
+            }
+        }
+    }
+}
+
+fn merge<T>(a: Link<T>, b: Link<T>) -> Link<T> {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        (Some(mut a), Some(mut b)) => {
+            if a.priority > b.priority {
+                a.push_down();
+                a.right = merge(a.right.take(), Some(b));
+                a.update();
+                Some(a)
+            } else {
+                b.push_down();
+                b.left = merge(Some(a), b.left.take());
+                b.update();
+                Some(b)
+            }
+        }
+    }
+}
+
+impl<T> Rope<T> {
+    pub fn new() -> Self {
+        Rope { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.root = merge(self.root.take(), Some(Node::new(value)));
+    }
+
+    pub fn get(&self, mut i: usize) -> Option<&T> {
+        let mut link = &self.root;
+        let mut flip = false;
+        while let Some(node) = link {
+            flip ^= node.reversed;
+            let (first, second) = if flip {
+                (&node.right, &node.left)
+            } else {
+                (&node.left, &node.right)
+            };
+            let first_size = size(first);
+            if i < first_size {
+                link = first;
+            } else if i == first_size {
+                return Some(&node.value);
+            } else {
+                i -= first_size + 1;
+                link = second;
+            }
+        }
+        None
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.len().checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Splits the rope in two at the given index, like `Vec::split_off`.
+    pub fn split_off(&mut self, at: usize) -> Rope<T> {
+        assert!(at <= self.len(), "split index out of bounds");
+        let (left, right) = split(self.root.take(), at);
+        self.root = left;
+        Rope { root: right }
+    }
+
+    /// Moves all elements of `other` to the end of `self`, like `Vec::append`.
+    pub fn append(&mut self, other: &mut Rope<T>) {
+        self.root = merge(self.root.take(), other.root.take());
+    }
+
+    pub fn reverse(&mut self) {
+        if let Some(root) = &mut self.root {
+            root.reversed = !root.reversed;
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.descend(&self.root, false);
+        iter
+    }
+}
+
+impl<T> Default for Rope<T> {
+    fn default() -> Self {
+        Rope::new()
+    }
+}
+
+impl<T> FromIterator<T> for Rope<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // Builds the treap in linear time, keeping the right spine on a stack:
+        // every node with a lower priority than the new one moves below it.
+        let mut spine: Vec<Box<Node<T>>> = Vec::new();
+        for value in iter {
+            let mut node = Node::new(value);
+            let mut below = None;
+            while spine.last().is_some_and(|top| top.priority < node.priority) {
+                let mut top = spine.pop().unwrap();
+                top.right = below;
+                top.update();
+                below = Some(top);
+            }
+            node.left = below;
+            spine.push(node);
+        }
+
+        let mut root = None;
+        while let Some(mut node) = spine.pop() {
+            node.right = root;
+            node.update();
+            root = Some(node);
+        }
+        Rope { root }
+    }
+}
+
+impl<T> From<Rope<T>> for Vec<T> {
+    fn from(rope: Rope<T>) -> Self {
+        fn collect<T>(link: Link<T>, out: &mut Vec<T>) {
+            if let Some(mut node) = link {
+                node.push_down();
+                let Node { value, left, right, .. } = *node;
+                collect(left, out);
+                out.push(value);
+                collect(right, out);
+            }
+        }
+
+        let mut out = Vec::with_capacity(rope.len());
+        collect(rope.root, &mut out);
+        out
+    }
+}
+
+pub struct Iter<'a, T> {
+    /// Nodes still to be yielded, along with whether their subtree is read
+    /// in reverse.
+    stack: Vec<(&'a Node<T>, bool)>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn descend(&mut self, mut link: &'a Link<T>, mut flip: bool) {
+        while let Some(node) = link {
+            flip ^= node.reversed;
+            self.stack.push((node, flip));
+            link = if flip { &node.right } else { &node.left };
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, flip) = self.stack.pop()?;
+        self.descend(if flip { &node.left } else { &node.right }, flip);
+        Some(&node.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift generator, so the tests don't depend on a crate.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    fn assert_same(rope: &Rope<u32>, vec: &[u32]) {
+        assert_eq!(rope.len(), vec.len());
+        assert_eq!(rope.is_empty(), vec.is_empty());
+        assert_eq!(rope.iter().copied().collect::<Vec<_>>(), vec);
+        assert_eq!(rope.last(), vec.last());
+        for (i, x) in vec.iter().enumerate() {
+            assert_eq!(rope.get(i), Some(x));
+        }
+        assert_eq!(rope.get(vec.len()), None);
+    }
+
+    #[test]
+    fn empty() {
+        let rope: Rope<u32> = Rope::new();
+        assert_same(&rope, &[]);
+        assert_eq!(Vec::from(rope), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn push_and_collect() {
+        let mut rope = Rope::new();
+        for x in 0..100 {
+            rope.push(x);
+        }
+        let vec: Vec<u32> = (0..100).collect();
+        assert_same(&rope, &vec);
+        assert_same(&vec.iter().copied().collect(), &vec);
+        assert_eq!(Vec::from(rope), vec);
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let mut rope: Rope<u32> = (0..10).collect();
+        let mut tail = rope.split_off(4);
+        assert_same(&rope, &[0, 1, 2, 3]);
+        assert_same(&tail, &[4, 5, 6, 7, 8, 9]);
+
+        let mut empty = tail.split_off(6);
+        assert_same(&empty, &[]);
+        rope.append(&mut empty);
+        rope.append(&mut tail);
+        assert_same(&tail, &[]);
+        assert_same(&rope, &(0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reverse_parts() {
+        let mut rope: Rope<u32> = (0..10).collect();
+        let mut middle = rope.split_off(3);
+        let mut tail = middle.split_off(4);
+        middle.reverse();
+        rope.append(&mut middle);
+        rope.append(&mut tail);
+        assert_same(&rope, &[0, 1, 2, 6, 5, 4, 3, 7, 8, 9]);
+
+        rope.reverse();
+        assert_same(&rope, &[9, 8, 7, 3, 4, 5, 6, 2, 1, 0]);
+        rope.reverse();
+        assert_eq!(Vec::from(rope), [0, 1, 2, 6, 5, 4, 3, 7, 8, 9]);
+    }
+
+    #[test]
+    fn random_operations_match_vec() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut ropes: Vec<Rope<u32>> = vec![(0..500).collect(), Rope::new(), (500..600).collect()];
+        let mut vecs: Vec<Vec<u32>> = vec![(0..500).collect(), Vec::new(), (500..600).collect()];
+
+        for _ in 0..2000 {
+            let (from, to) = (rng.below(3), rng.below(3));
+            if from == to {
+                ropes[from].reverse();
+                vecs[from].reverse();
+                continue;
+            }
+            let at = rng.below(vecs[from].len() + 1);
+            let mut pile = ropes[from].split_off(at);
+            let mut vec_pile = vecs[from].split_off(at);
+            if rng.below(2) == 0 {
+                pile.reverse();
+                vec_pile.reverse();
+            }
+            ropes[to].append(&mut pile);
+            vecs[to].append(&mut vec_pile);
+        }
+        for (rope, vec) in ropes.iter().zip(&vecs) {
+            assert_same(rope, vec);
+        }
+    }
+}